@@ -38,6 +38,26 @@ impl AABB {
         return true;
     }
 
+    // The part of [t_min, t_max] the ray spends inside the box
+    pub fn hit_interval(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut tmin = t_min;
+        let mut tmax = t_max;
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction().comp(a);
+            let mut t0 = (self.min.comp(a) - r.origin().comp(a)) * inv_d;
+            let mut t1 = (self.max.comp(a) - r.origin().comp(a)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            tmin = f64::max(t0, tmin);
+            tmax = f64::min(t1, tmax);
+            if tmax <= tmin {
+                return None;
+            }
+        }
+        Some((tmin, tmax))
+    }
+
     pub fn surrounding_box(box0: AABB, box1: AABB) -> AABB {
         let small = Vec3::new(
             f64::min(box0.min().x(), box1.min().x()),
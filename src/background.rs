@@ -0,0 +1,54 @@
+use crate::environment::EnvironmentMap;
+use crate::rand::Rng;
+use crate::sky::Sky;
+use crate::vec::Vec3;
+
+// What rays see when they leave the scene without hitting anything
+#[derive(Clone)]
+pub enum Background {
+    Solid(Vec3),
+    Gradient(Vec3, Vec3), // Horizon, Zenith
+    Sky(Sky),             // Physical daylight, pair with its sun light
+    Environment(EnvironmentMap),
+}
+
+impl Background {
+    // The blue sky the scenes have always used
+    pub fn sky() -> Self {
+        Background::Gradient(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.7, 1.0))
+    }
+
+    pub fn color(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient(horizon, zenith) => {
+                let unit_direction = direction.to_unit();
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * *horizon + t * *zenith
+            }
+            Background::Sky(sky) => sky.color(direction),
+            Background::Environment(map) => map.color(direction),
+        }
+    }
+
+    // Picks a direction to sample the background along for direct lighting,
+    // with its pdf over solid angle. Only backgrounds with bright spots worth
+    // finding are sampled, the rest are left to bounced rays.
+    pub fn sample<T: Rng>(&self, rng: &mut T) -> Option<(Vec3, f64)> {
+        match self {
+            Background::Environment(map) => Some(map.sample(rng)),
+            _ => None,
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Background::Environment(_))
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        match self {
+            Background::Environment(map) => map.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use crate::camera::Camera;
+use crate::lights::LightType;
+use crate::materials::{HitRecord, Material};
+use crate::medium::free_flight;
+use crate::nested::MediumStack;
+use crate::rand::Rng;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::world::World;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Bidirectional path tracing (Veach 1997). Each sample traces one path from
+// the camera and one from a light, then joins every prefix of one to every
+// prefix of the other. Paths found several ways are weighted against each
+// other with the power heuristic, so each is counted once through whichever
+// way finds it best. Light paths joined straight to the camera land on other
+// pixels, and are splatted onto a shared film.
+//
+// Scattering inside media is followed but never joined to, paths joined
+// between two vertices aren't dimmed by any medium around them, and
+// environment maps are only found by camera paths escaping the scene.
+
+// Most bounces either path takes
+const MAX_VERTICES: usize = 10;
+
+#[derive(Clone)]
+enum Kind<'a> {
+    Camera,
+    Light(&'a LightType),
+    Surface(HitRecord<'a>, Ray), // Hit, and the ray that arrived at it
+    Medium,                      // Scattered inside a medium, never joined to
+}
+
+#[derive(Clone)]
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    p: Vec3,
+    normal: Option<Vec3>, // None for points like a pinhole or point light
+    beta: Vec3,           // Throughput of the path up to here
+    delta: bool,          // Scattered by a mirror-like surface
+    pdf_fwd: f64,         // Area density of reaching here along the path
+    pdf_rev: f64,         // Area density of reaching here from the other end
+}
+
+// Light reaching the film from light paths, shared between threads
+pub struct SplatFilm {
+    width: usize,
+    height: usize,
+    pixels: Vec<[AtomicU64; 3]>,
+}
+
+impl SplatFilm {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        pixels.resize_with(width * height, || {
+            [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)]
+        });
+        SplatFilm {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Adds light at film coordinates (s, t), as the camera takes them
+    fn add(&self, s: f64, t: f64, light: Vec3) {
+        let x = ((s * self.width as f64) as usize).min(self.width - 1);
+        let y = ((t * self.height as f64) as usize).min(self.height - 1);
+        let pixel = &self.pixels[y * self.width + x];
+        for (channel, value) in pixel.iter().zip(&[light.x(), light.y(), light.z()]) {
+            let _ = channel.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        let [r, g, b] = &self.pixels[y * self.width + x];
+        Vec3::new(
+            f64::from_bits(r.load(Ordering::Relaxed)),
+            f64::from_bits(g.load(Ordering::Relaxed)),
+            f64::from_bits(b.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+// Light arriving along the camera ray `ray`. Light that reaches other pixels
+// is added to `film`, scaled by `weight`.
+pub fn trace<T: Rng>(
+    ray: &Ray,
+    camera: &Camera,
+    world: &World,
+    film: &SplatFilm,
+    weight: Vec3,
+    rng: &mut T,
+) -> Vec3 {
+    let black = Vec3::new(0.0, 0.0, 0.0);
+    let wavelength = ray.wavelength();
+    let ray = Ray::new(ray.origin(), ray.direction().to_unit()).with_wavelength(wavelength);
+
+    let mut camera_path = vec![Vertex {
+        kind: Kind::Camera,
+        p: ray.origin(),
+        normal: None,
+        beta: Vec3::new(1.0, 1.0, 1.0),
+        delta: false,
+        pdf_fwd: 1.0,
+        pdf_rev: 0.0,
+    }];
+    let pdf_dir = camera.pdf_direction(ray.origin(), ray.direction());
+    let beta = Vec3::new(1.0, 1.0, 1.0);
+    let escaped = random_walk(world, ray, beta, pdf_dir, &mut camera_path, rng);
+
+    // Only camera paths find the background
+    let mut total = match escaped {
+        Some((ray, beta)) => beta * world.background().color(ray.direction()),
+        None => black,
+    };
+
+    let mut light_path = Vec::new();
+    if let Some((light, probability)) = world.sample_light_by_power(rng) {
+        if let Some(emission) = light.sample_emission(rng) {
+            let pdf_origin = probability * emission.pdf_position;
+            light_path.push(Vertex {
+                kind: Kind::Light(light),
+                p: emission.origin,
+                normal: emission.normal,
+                beta: emission.radiance / pdf_origin,
+                delta: false,
+                pdf_fwd: pdf_origin,
+                pdf_rev: 0.0,
+            });
+            let cosine = emission
+                .normal
+                .map_or(1.0, |n| Vec3::dot(n, emission.direction).abs());
+            let beta = emission.radiance * cosine / (pdf_origin * emission.pdf_direction);
+            let ray = Ray::new(emission.origin, emission.direction).with_wavelength(wavelength);
+            random_walk(
+                world,
+                ray,
+                beta,
+                emission.pdf_direction,
+                &mut light_path,
+                rng,
+            );
+        }
+    }
+
+    for t in 1..=camera_path.len() {
+        for s in 0..=light_path.len() {
+            if s + t < 2 || (s == 1 && t == 1) {
+                continue;
+            }
+            if t == 1 {
+                if let Some((film_s, film_t, light)) =
+                    connect_to_camera(&light_path, s, camera, world, rng)
+                {
+                    film.add(film_s, film_t, weight * light);
+                }
+            } else {
+                total = total + connect(&light_path, &camera_path, s, t, camera, world, rng);
+            }
+        }
+        // Lights at infinity can't be reached from the light end, so sampling
+        // them is the only way to find them
+        if t >= 2 {
+            total = total + infinite_lights(&camera_path[t - 1], world, rng);
+        }
+    }
+
+    total
+}
+
+// Extends `path` by bouncing `ray` through the scene. Returns the ray and
+// throughput if the path escapes.
+fn random_walk<'a, T: Rng>(
+    world: &'a World,
+    mut ray: Ray,
+    mut beta: Vec3,
+    mut pdf_dir: f64,
+    path: &mut Vec<Vertex<'a>>,
+    rng: &mut T,
+) -> Option<(Ray, Vec3)> {
+    let mut media = MediumStack::new();
+    while path.len() < MAX_VERTICES {
+        let mut rec = match world.hit(&ray, 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return Some((ray, beta)),
+        };
+
+        // As in the path tracer, though scattering in a medium is treated
+        // like a mirror, with no density to weigh it by
+        if let Some((sigma_s, sigma_a)) = media.interior() {
+            let (scatter_at, weight) = free_flight(sigma_s, sigma_a, rec.t, rng);
+            beta = beta * weight;
+            if let Some(distance) = scatter_at {
+                let mut vertex = Vertex {
+                    kind: Kind::Medium,
+                    p: ray.at(distance),
+                    normal: None,
+                    beta,
+                    delta: true,
+                    pdf_fwd: 0.0,
+                    pdf_rev: 0.0,
+                };
+                vertex.pdf_fwd = to_area(pdf_dir, &path[path.len() - 1], &vertex);
+                let n = path.len();
+                path[n - 1].pdf_rev = 0.0;
+                path.push(vertex);
+                ray = Ray::new(ray.at(distance), Vec3::random_unit_vector(rng))
+                    .with_wavelength(ray.wavelength());
+                pdf_dir = 0.0;
+                continue;
+            }
+        }
+        match media.outside_ior(&rec, ray.wavelength()) {
+            Some(ior) => rec.outside_ior = ior,
+            None => {
+                media.cross(rec.mat, rec.front_face);
+                ray = Ray::new(rec.p, ray.direction()).with_wavelength(ray.wavelength());
+                continue;
+            }
+        }
+
+        let mut vertex = Vertex {
+            kind: Kind::Surface(rec.clone(), ray),
+            p: rec.p,
+            normal: Some(rec.normal),
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        };
+        vertex.pdf_fwd = to_area(pdf_dir, &path[path.len() - 1], &vertex);
+        path.push(vertex);
+
+        let (attenuation, scattered) = rec.mat.scatter(&ray, &rec, rng)?;
+        let direction = scattered.direction().to_unit();
+        let scattered = Ray::new(scattered.origin(), direction).with_wavelength(ray.wavelength());
+        if (Vec3::dot(direction, rec.outward_normal()) > 0.0) != rec.front_face {
+            media.cross(rec.mat, rec.front_face);
+        }
+
+        // Mirror-like scattering has no density to weigh it by
+        let (pdf_fwd, pdf_rev, delta) = match rec.mat.eval(&ray, &rec, direction) {
+            Some((_, pdf)) => {
+                let back =
+                    Ray::new(rec.p + direction, -direction).with_wavelength(ray.wavelength());
+                let reverse = rec
+                    .mat
+                    .eval(&back, &facing(&rec, -direction), -ray.direction())
+                    .map_or(0.0, |(_, pdf)| pdf);
+                (pdf, reverse, false)
+            }
+            None => (0.0, 0.0, true),
+        };
+
+        beta = beta * attenuation;
+        let n = path.len();
+        path[n - 1].delta = delta;
+        path[n - 2].pdf_rev = to_area(pdf_rev, &path[n - 1], &path[n - 2]);
+        if beta == Vec3::new(0.0, 0.0, 0.0) {
+            return None;
+        }
+        pdf_dir = pdf_fwd;
+        ray = scattered;
+    }
+    None
+}
+
+// The hit as seen by a ray arriving along `direction`, since materials expect
+// the normal to face the incoming ray
+fn facing<'a>(rec: &HitRecord<'a>, direction: Vec3) -> HitRecord<'a> {
+    let mut rec = rec.clone();
+    if Vec3::dot(direction, rec.normal) > 0.0 {
+        rec.normal = -rec.normal;
+        rec.front_face = !rec.front_face;
+    }
+    rec
+}
+
+// Converts a density over solid angle at `from` to one over area at `to`
+fn to_area(pdf_dir: f64, from: &Vertex, to: &Vertex) -> f64 {
+    let d = to.p - from.p;
+    let dist2 = d.length_squared();
+    if dist2 == 0.0 {
+        return 0.0;
+    }
+    match to.normal {
+        Some(n) => pdf_dir * Vec3::dot(n, d).abs() / (dist2 * dist2.sqrt()),
+        None => pdf_dir / dist2,
+    }
+}
+
+fn is_black(v: Vec3) -> bool {
+    v == Vec3::new(0.0, 0.0, 0.0)
+}
+
+impl<'a> Vertex<'a> {
+    // BSDF times cosine for light leaving toward `to`, arriving the way the
+    // path came. None at mirror-like surfaces.
+    fn f(&self, to: Vec3) -> Option<Vec3> {
+        match &self.kind {
+            Kind::Surface(rec, ray_in) => rec.mat.eval(ray_in, rec, to - self.p).map(|(f, _)| f),
+            _ => None,
+        }
+    }
+
+    // Area density at `next` of this vertex scattering toward it, when the
+    // path arrived from `prev`
+    fn pdf(&self, camera: &Camera, world: &World, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.p - self.p;
+        let pdf_dir = match &self.kind {
+            Kind::Camera => camera.pdf_direction(self.p, direction),
+            Kind::Light(light) => light.pdf_emission(self.p, direction).1,
+            Kind::Surface(rec, ray_in) => {
+                let prev = match prev {
+                    Some(prev) => prev,
+                    None => return 0.0,
+                };
+                let incoming =
+                    Ray::new(prev.p, self.p - prev.p).with_wavelength(ray_in.wavelength());
+                let rec = facing(rec, incoming.direction());
+                match rec.mat.eval(&incoming, &rec, direction) {
+                    Some((_, pdf)) => pdf,
+                    None => 0.0,
+                }
+            }
+            Kind::Medium => 0.0,
+        };
+        to_area(pdf_dir, self, next)
+    }
+
+    // The sampled light this vertex lies on, and its probability of being
+    // picked
+    fn emitter(&self, world: &'a World) -> Option<(&'a LightType, f64)> {
+        match &self.kind {
+            Kind::Surface(rec, _) => world.emitter(rec),
+            _ => None,
+        }
+    }
+
+    // Area density of a light path starting here
+    fn pdf_light_origin(&self, world: &World, next: &Vertex) -> f64 {
+        match self.emitter(world) {
+            Some((light, probability)) => {
+                probability * light.pdf_emission(self.p, next.p - self.p).0
+            }
+            None => 0.0,
+        }
+    }
+
+    // Area density at `next` of a light path starting here heading for it
+    fn pdf_light(&self, world: &World, next: &Vertex) -> f64 {
+        match self.emitter(world) {
+            Some((light, _)) => to_area(light.pdf_emission(self.p, next.p - self.p).1, self, next),
+            None => 0.0,
+        }
+    }
+}
+
+fn transmittance(world: &World, from: Vec3, to: Vec3, wavelength: Option<f64>) -> f64 {
+    let d = to - from;
+    let distance = d.length();
+    let shadow = Ray::new(from, d / distance).with_wavelength(wavelength);
+    world.transmittance(&shadow, distance * (1.0 - 1e-6))
+}
+
+fn wavelength(v: &Vertex) -> Option<f64> {
+    match &v.kind {
+        Kind::Surface(_, ray) => ray.wavelength(),
+        _ => None,
+    }
+}
+
+// Joins the first `s` vertices of the light path to the first `t` of the
+// camera path, with `t` at least 2
+fn connect<T: Rng>(
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+    camera: &Camera,
+    world: &World,
+    rng: &mut T,
+) -> Vec3 {
+    let black = Vec3::new(0.0, 0.0, 0.0);
+    let pt = &camera_path[t - 1];
+    let wl = wavelength(pt);
+
+    let (light, sampled) = if s == 0 {
+        // The camera path found a light on its own
+        let rec = match &pt.kind {
+            Kind::Surface(rec, _) => rec,
+            _ => return black,
+        };
+        let emitted = rec.mat.emitted(rec);
+        if is_black(emitted) {
+            return black;
+        }
+        if pt.emitter(world).is_none() {
+            // Emitters that aren't sampled can only be found this way
+            return pt.beta * emitted;
+        }
+        (pt.beta * emitted, None)
+    } else if s == 1 {
+        // Sample a point on a light, rather than using where the light path
+        // started
+        let (light, probability) = match world.sample_light_by_power(rng) {
+            Some(picked) => picked,
+            None => return black,
+        };
+        let sample = match light.sample(pt.p, rng) {
+            Some(sample) => sample,
+            None => return black,
+        };
+        let f = match pt.f(pt.p + sample.direction) {
+            Some(f) if !is_black(f) => f,
+            _ => return black,
+        };
+        let p = pt.p + sample.direction * sample.distance;
+        let visibility = transmittance(world, pt.p, p, wl);
+        if visibility <= 0.0 {
+            return black;
+        }
+        let normal = match light {
+            LightType::Sphere(center, radius, _) => Some((p - *center) / *radius),
+            _ => None,
+        };
+        let pdf_origin = probability * light.pdf_emission(p, pt.p - p).0;
+        let vertex = Vertex {
+            kind: Kind::Light(light),
+            p,
+            normal,
+            beta: black,
+            delta: false,
+            pdf_fwd: pdf_origin,
+            pdf_rev: 0.0,
+        };
+        (
+            visibility * pt.beta * f * sample.radiance / probability,
+            Some(vertex),
+        )
+    } else {
+        let qs = &light_path[s - 1];
+        let (fq, fp) = match (qs.f(pt.p), pt.f(qs.p)) {
+            (Some(fq), Some(fp)) if !is_black(fq) && !is_black(fp) => (fq, fp),
+            _ => return black,
+        };
+        let visibility = transmittance(world, pt.p, qs.p, wl);
+        if visibility <= 0.0 {
+            return black;
+        }
+        let dist2 = (qs.p - pt.p).length_squared();
+        (visibility * qs.beta * fq * fp * pt.beta / dist2, None)
+    };
+
+    if is_black(light) {
+        return black;
+    }
+    light * mis_weight(light_path, camera_path, sampled, s, t, camera, world)
+}
+
+// Joins the light path's `s` vertices straight to the camera. Returns where
+// on the film the light lands, and how much.
+fn connect_to_camera<T: Rng>(
+    light_path: &[Vertex],
+    s: usize,
+    camera: &Camera,
+    world: &World,
+    rng: &mut T,
+) -> Option<(f64, f64, Vec3)> {
+    let qs = &light_path[s - 1];
+    let lens = camera.sample_lens(rng);
+    let (film_s, film_t) = camera.project(lens, qs.p)?;
+    let f = qs.f(lens).filter(|f| !is_black(*f))?;
+    let visibility = transmittance(world, qs.p, lens, wavelength(qs));
+    if visibility <= 0.0 {
+        return None;
+    }
+    let to_lens = lens - qs.p;
+    let importance = visibility * camera.importance(-to_lens, to_lens.length());
+    let vertex = Vertex {
+        kind: Kind::Camera,
+        p: lens,
+        normal: None,
+        beta: Vec3::new(1.0, 1.0, 1.0),
+        delta: false,
+        pdf_fwd: 0.0,
+        pdf_rev: 0.0,
+    };
+    let light = qs.beta * f * importance;
+    let weight = mis_weight(light_path, &[], Some(vertex), s, 1, camera, world);
+    Some((film_s, film_t, light * weight))
+}
+
+// Sun-like lights that can't be hit, sampled from the end of the camera path
+fn infinite_lights<T: Rng>(pt: &Vertex, world: &World, rng: &mut T) -> Vec3 {
+    let mut total = Vec3::new(0.0, 0.0, 0.0);
+    for light in world.lights().iter().filter(|light| light.is_infinite()) {
+        let sample = match light.sample(pt.p, rng) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let f = match pt.f(pt.p + sample.direction) {
+            Some(f) if !is_black(f) => f,
+            _ => continue,
+        };
+        let shadow = Ray::new(pt.p, sample.direction).with_wavelength(wavelength(pt));
+        let visibility = world.transmittance(&shadow, sample.distance);
+        total = total + visibility * pt.beta * f * sample.radiance;
+    }
+    total
+}
+
+// Power heuristic weight of the strategy joining `s` light vertices to `t`
+// camera vertices, against every other way of making the same path. Works on
+// ratios of the densities at each vertex, as in Veach's thesis and PBRT.
+fn mis_weight(
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    sampled: Option<Vertex>,
+    s: usize,
+    t: usize,
+    camera: &Camera,
+    world: &World,
+) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+    let mut qs: Vec<Vertex> = light_path[..s].to_vec();
+    let mut pt: Vec<Vertex> = if t == 1 {
+        Vec::new()
+    } else {
+        camera_path[..t].to_vec()
+    };
+    if let Some(vertex) = sampled {
+        if s == 1 {
+            qs[0] = vertex;
+        } else {
+            pt.push(vertex);
+        }
+    }
+
+    // The joined vertices are never mirror-like, or they couldn't be joined
+    if t > 0 {
+        pt[t - 1].delta = false;
+    }
+    if s > 0 {
+        qs[s - 1].delta = false;
+    }
+
+    // Densities of generating the joined vertices from the other side
+    let rev_pt = if s > 0 {
+        let prev = if s > 1 { Some(&qs[s - 2]) } else { None };
+        qs[s - 1].pdf(camera, world, prev, &pt[t - 1])
+    } else {
+        pt[t - 1].pdf_light_origin(world, &pt[t - 2])
+    };
+    let rev_pt_prev = if t > 1 {
+        Some(if s > 0 {
+            pt[t - 1].pdf(camera, world, Some(&qs[s - 1]), &pt[t - 2])
+        } else {
+            pt[t - 1].pdf_light(world, &pt[t - 2])
+        })
+    } else {
+        None
+    };
+    let rev_qs = if s > 0 {
+        let prev = if t > 1 { Some(&pt[t - 2]) } else { None };
+        Some(pt[t - 1].pdf(camera, world, prev, &qs[s - 1]))
+    } else {
+        None
+    };
+    let rev_qs_prev = if s > 1 {
+        Some(qs[s - 1].pdf(camera, world, Some(&pt[t - 1]), &qs[s - 2]))
+    } else {
+        None
+    };
+    pt[t - 1].pdf_rev = rev_pt;
+    if let Some(pdf) = rev_pt_prev {
+        pt[t - 2].pdf_rev = pdf;
+    }
+    if let Some(pdf) = rev_qs {
+        qs[s - 1].pdf_rev = pdf;
+    }
+    if let Some(pdf) = rev_qs_prev {
+        qs[s - 2].pdf_rev = pdf;
+    }
+
+    // Mirror-like vertices have no density, and count as 1 in the ratios
+    let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+    let mut sum = 0.0;
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap(pt[i].pdf_rev) / remap(pt[i].pdf_fwd);
+        if !pt[i].delta && !pt[i - 1].delta {
+            sum += ri * ri;
+        }
+    }
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap(qs[i].pdf_rev) / remap(qs[i].pdf_fwd);
+        let delta_before = if i > 0 {
+            qs[i - 1].delta
+        } else {
+            match qs[0].kind {
+                Kind::Light(light) => light.is_delta(),
+                _ => false,
+            }
+        };
+        if !qs[i].delta && !delta_before {
+            sum += ri * ri;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
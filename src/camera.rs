@@ -59,6 +59,61 @@ impl Camera {
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
         )
     }
+
+    // A point on the lens, as get_ray picks for its rays to start from
+    pub fn sample_lens<T: Rng>(&self, rng: &mut T) -> Vec3 {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk(rng);
+        self.origin + self.u * rd.x() + self.v * rd.y()
+    }
+
+    // Where on the film `p` is seen through `lens`, as the (s, t) get_ray
+    // takes, or None if it's out of view
+    pub fn project(&self, lens: Vec3, p: Vec3) -> Option<(f64, f64)> {
+        let d = p - lens;
+        let depth = Vec3::dot(d, -self.w);
+        if depth <= 0.0 {
+            return None;
+        }
+        // Rays through one point of the focus plane meet on the same pixel
+        let q = lens + d * (self.focus_dist() / depth) - self.lower_left_corner;
+        let s = Vec3::dot(q, self.horizontal) / self.horizontal.length_squared();
+        let t = Vec3::dot(q, self.vertical) / self.vertical.length_squared();
+        if s < 0.0 || s > 1.0 || t < 0.0 || t > 1.0 {
+            return None;
+        }
+        Some((s, t))
+    }
+
+    // Density over solid angle of get_ray sending a ray from `lens` along
+    // `direction`, with the film spread evenly over the whole image
+    pub fn pdf_direction(&self, lens: Vec3, direction: Vec3) -> f64 {
+        if self.project(lens, lens + direction).is_none() {
+            return 0.0;
+        }
+        let cos_theta = Vec3::dot(direction.to_unit(), -self.w);
+        1.0 / (self.film_area() * cos_theta * cos_theta * cos_theta)
+    }
+
+    // Importance the camera gives a point `distance` away from `lens` along
+    // `direction`, divided by the density of picking that lens point to
+    // reach it
+    pub fn importance(&self, direction: Vec3, distance: f64) -> f64 {
+        let cos_theta = Vec3::dot(direction.to_unit(), -self.w);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (self.film_area() * cos_theta * cos_theta * cos_theta * distance * distance)
+    }
+
+    fn focus_dist(&self) -> f64 {
+        Vec3::dot(self.origin - self.lower_left_corner, self.w)
+    }
+
+    // Area of the film scaled to one unit in front of the lens
+    fn film_area(&self) -> f64 {
+        let focus_dist = self.focus_dist();
+        self.horizontal.length() * self.vertical.length() / (focus_dist * focus_dist)
+    }
 }
 
 impl CameraOptions {
@@ -106,4 +161,34 @@ impl CameraOptions {
             vup: Vec3::new(0.0, 1.0, 0.0),
         }
     }
+
+    pub fn cornell(pm: &PixMap) -> CameraOptions {
+        let look_at = Vec3::new(278.0, 278.0, 0.0);
+        let look_from = Vec3::new(278.0, 278.0, -800.0);
+
+        CameraOptions {
+            fov: 40.0,
+            aspect: pm.aspect(),
+            aperature: 0.0,
+            focus_dist: (look_from - look_at).length(),
+            look_at: look_at,
+            look_from: look_from,
+            vup: Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    pub fn wide(pm: &PixMap) -> CameraOptions {
+        let look_at = Vec3::new(0.0, -0.1, -1.0);
+        let look_from = Vec3::new(0.0, 0.6, 1.5);
+
+        CameraOptions {
+            fov: 50.0,
+            aspect: pm.aspect(),
+            aperature: 0.0,
+            focus_dist: (look_from - look_at).length(),
+            look_at: look_at,
+            look_from: look_from,
+            vup: Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
 }
@@ -40,6 +40,12 @@ impl Samples {
         self.vector = self.vector + sample; // TODO make AddAssign impl?
         self.num_samples += 1;
     }
+
+    // Light landing on the pixel from paths traced out of the lights, which
+    // doesn't count as a sample of its own
+    pub fn add_splat(&mut self, light: Vec3) {
+        self.vector = self.vector + light;
+    }
 }
 
 fn clamp(x: f64, min: f64, max: f64) -> f64 {
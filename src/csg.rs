@@ -0,0 +1,120 @@
+use crate::{
+    aabb::AABB,
+    hittable::{Hittable, HittableType},
+    materials::{HitRecord, MaterialType},
+    ray::Ray,
+    vec::Vec3,
+};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference, // Left minus right
+}
+
+impl CsgOp {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// Combines two closed hittables. Both children share the material of the node.
+#[derive(Clone)]
+pub struct Csg {
+    op: CsgOp,
+    left: Arc<HittableType>,
+    right: Arc<HittableType>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, left: Arc<HittableType>, right: Arc<HittableType>) -> Self {
+        Csg { op, left, right }
+    }
+
+    // Walks the boundary crossings of both children in order along the ray,
+    // tracking whether the ray is inside each one, and returns the first
+    // crossing that changes whether it is inside the combined solid.
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let mut left_rec = self.left.hit(mat, ray, t_min, f64::INFINITY);
+        let mut right_rec = self.right.hit(mat, ray, t_min, f64::INFINITY);
+
+        // If the first crossing is an exit, the ray started inside
+        let mut in_left = left_rec.as_ref().map_or(false, |rec| !rec.front_face);
+        let mut in_right = right_rec.as_ref().map_or(false, |rec| !rec.front_face);
+
+        loop {
+            let left_t = left_rec.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+            let right_t = right_rec.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+            let t = left_t.min(right_t);
+            if t >= t_max {
+                return None;
+            }
+
+            let before = self.op.inside(in_left, in_right);
+            let from_left = left_t <= right_t;
+            if from_left {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+            let after = self.op.inside(in_left, in_right);
+
+            if before != after {
+                let mut rec = if from_left {
+                    left_rec.take()
+                } else {
+                    right_rec.take()
+                }
+                .expect("CSG crossing without a hit record");
+                // The normal already faces the ray, only the side needs fixing
+                // up for surfaces that are inverted by the operation.
+                rec.front_face = after;
+                return Some(rec);
+            }
+
+            if from_left {
+                left_rec = self.left.hit(mat, ray, t, f64::INFINITY);
+            } else {
+                right_rec = self.right.hit(mat, ray, t, f64::INFINITY);
+            }
+        }
+    }
+
+    pub fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        let left = self.left.bounding_box(t0, t1);
+        let right = self.right.bounding_box(t0, t1);
+
+        match self.op {
+            CsgOp::Union => Some(AABB::surrounding_box(left?, right?)),
+            CsgOp::Intersection => match (left, right) {
+                (Some(l), Some(r)) => Some(AABB::new(
+                    Vec3::new(
+                        l.min().x().max(r.min().x()),
+                        l.min().y().max(r.min().y()),
+                        l.min().z().max(r.min().z()),
+                    ),
+                    Vec3::new(
+                        l.max().x().min(r.max().x()),
+                        l.max().y().min(r.max().y()),
+                        l.max().z().min(r.max().z()),
+                    ),
+                )),
+                (Some(bb), None) | (None, Some(bb)) => Some(bb),
+                (None, None) => None,
+            },
+            CsgOp::Difference => left,
+        }
+    }
+}
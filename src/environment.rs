@@ -0,0 +1,107 @@
+use crate::image::{Filter, Image, Wrap};
+use crate::rand::Rng;
+use crate::vec::Vec3;
+use std::f64::consts;
+use std::sync::Arc;
+
+// An equirectangular (latitude-longitude) image surrounding the scene, with
+// distributions for picking directions in proportion to how bright they are
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    image: Arc<Image>,
+    intensity: f64,
+    rows: Vec<f64>,    // Cumulative weight of the rows, top to bottom
+    columns: Vec<f64>, // Cumulative weight along each row
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Arc<Image>, intensity: f64) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let mut rows = Vec::with_capacity(height + 1);
+        let mut columns = Vec::with_capacity((width + 1) * height);
+        rows.push(0.0);
+        for y in 0..height {
+            // Rows near the poles cover less of the sphere
+            let sin_theta = ((y as f64 + 0.5) / height as f64 * consts::PI).sin();
+            let mut sum = 0.0;
+            columns.push(0.0);
+            for x in 0..width {
+                // Dark texels keep a little weight, so filtered lookups that
+                // bleed into them can still be sampled
+                sum += (image.pixel(x, y).luminance() + 1e-3) * sin_theta;
+                columns.push(sum);
+            }
+            let total = rows[y] + sum;
+            rows.push(total);
+        }
+
+        EnvironmentMap {
+            image,
+            intensity,
+            rows,
+            columns,
+        }
+    }
+
+    pub fn color(&self, direction: Vec3) -> Vec3 {
+        let d = direction.to_unit();
+        let u = (d.z().atan2(d.x()) + consts::PI) / (2.0 * consts::PI);
+        let v = 1.0 - d.y().max(-1.0).min(1.0).acos() / consts::PI;
+        // Around the poles stay on the nearest row, repeating would blend the
+        // zenith into the nadir
+        let half_texel = 0.5 / self.image.height() as f64;
+        let v = v.max(half_texel).min(1.0 - half_texel);
+        self.intensity * self.image.sample(u, v, Filter::Bilinear, Wrap::Repeat)
+    }
+
+    // Picks a direction toward a texel chosen by its brightness, returning the
+    // direction and its pdf over solid angle
+    pub fn sample<T: Rng>(&self, rng: &mut T) -> (Vec3, f64) {
+        let (width, height) = (self.image.width(), self.image.height());
+        let total = self.rows[height];
+
+        let y = find_interval(&self.rows, rng.gen::<f64>() * total);
+        let row = &self.columns[y * (width + 1)..(y + 1) * (width + 1)];
+        let x = find_interval(row, rng.gen::<f64>() * row[width]);
+
+        // Uniform within the texel
+        let u = (x as f64 + rng.gen::<f64>()) / width as f64;
+        let v = (y as f64 + rng.gen::<f64>()) / height as f64;
+        let phi = u * 2.0 * consts::PI - consts::PI;
+        let theta = v * consts::PI;
+        let direction = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        );
+        (direction, self.pdf(direction))
+    }
+
+    // Density over solid angle of `sample` picking `direction`
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let (width, height) = (self.image.width(), self.image.height());
+        let d = direction.to_unit();
+        let u = (d.z().atan2(d.x()) + consts::PI) / (2.0 * consts::PI);
+        let theta = d.y().max(-1.0).min(1.0).acos();
+        let sin_theta = theta.sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((theta / consts::PI * height as f64) as usize).min(height - 1);
+        let start = y * (width + 1);
+        let weight = self.columns[start + x + 1] - self.columns[start + x];
+        let probability = weight / self.rows[height];
+
+        // Texels are equal areas of the (u, v) square, which maps onto the
+        // sphere with a Jacobian of 2 pi^2 sin(theta)
+        probability * (width * height) as f64 / (2.0 * consts::PI * consts::PI * sin_theta)
+    }
+}
+
+// Index of the interval of a cumulative table that `value` falls in
+pub fn find_interval(cdf: &[f64], value: f64) -> usize {
+    let upper = cdf.partition_point(|c| *c <= value);
+    upper.max(1).min(cdf.len() - 1) - 1
+}
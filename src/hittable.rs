@@ -1,14 +1,33 @@
+use crate::csg::{Csg, CsgOp};
+use crate::instance::Instance;
 use crate::materials::{HitRecord, MaterialType};
+use crate::medium::{ConstantMedium, GridMedium, VoxelGrid};
+use crate::mesh::Mesh;
 use crate::ray::Ray;
-use crate::solids::Sphere;
+use crate::solids::{Cone, Cuboid, Cylinder, Disk, Plane, Sphere, Torus, XYRect, XZRect, YZRect};
+use crate::transform::Transform;
 use crate::{aabb::AABB, vec::Vec3};
 use rand::distributions::{Distribution, Uniform};
 use rand::Rng;
 use std::{cmp::Ordering, f64::consts, sync::Arc};
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum HittableType {
     Sphere(Sphere),
+    Mesh(Mesh),
+    XYRect(XYRect),
+    XZRect(XZRect),
+    YZRect(YZRect),
+    Box(Cuboid),
+    Plane(Plane),
+    Disk(Disk),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Instance(Instance),
+    Csg(Csg),
+    ConstantMedium(ConstantMedium),
+    GridMedium(GridMedium),
 }
 
 impl HittableType {
@@ -16,6 +35,74 @@ impl HittableType {
         HittableType::Sphere(Sphere::new(center, radius))
     }
 
+    // Triangles over `positions`, wound counter-clockwise seen from outside
+    pub fn mesh(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>) -> Self {
+        HittableType::Mesh(Mesh::new(positions, triangles))
+    }
+
+    pub fn xy_rect(x0: f64, x1: f64, y0: f64, y1: f64, k: f64) -> Self {
+        HittableType::XYRect(XYRect::new(x0, x1, y0, y1, k))
+    }
+
+    pub fn xz_rect(x0: f64, x1: f64, z0: f64, z1: f64, k: f64) -> Self {
+        HittableType::XZRect(XZRect::new(x0, x1, z0, z1, k))
+    }
+
+    pub fn yz_rect(y0: f64, y1: f64, z0: f64, z1: f64, k: f64) -> Self {
+        HittableType::YZRect(YZRect::new(y0, y1, z0, z1, k))
+    }
+
+    // Axis-aligned box spanning the two opposite corners
+    pub fn cuboid(p0: Vec3, p1: Vec3) -> Self {
+        HittableType::Box(Cuboid::new(p0, p1))
+    }
+
+    pub fn plane(point: Vec3, normal: Vec3) -> Self {
+        HittableType::Plane(Plane::new(point, normal))
+    }
+
+    pub fn disk(center: Vec3, normal: Vec3, radius: f64) -> Self {
+        HittableType::Disk(Disk::new(center, normal, radius))
+    }
+
+    pub fn cylinder(base: Vec3, radius: f64, height: f64, capped: bool) -> Self {
+        HittableType::Cylinder(Cylinder::new(base, radius, height, capped))
+    }
+
+    pub fn cone(base: Vec3, radius: f64, height: f64, capped: bool) -> Self {
+        HittableType::Cone(Cone::new(base, radius, height, capped))
+    }
+
+    pub fn torus(center: Vec3, major: f64, minor: f64) -> Self {
+        HittableType::Torus(Torus::new(center, major, minor))
+    }
+
+    pub fn instance(object: Arc<HittableType>, transform: Transform) -> Self {
+        HittableType::Instance(Instance::new(object, transform))
+    }
+
+    pub fn union(left: Arc<HittableType>, right: Arc<HittableType>) -> Self {
+        HittableType::Csg(Csg::new(CsgOp::Union, left, right))
+    }
+
+    pub fn intersection(left: Arc<HittableType>, right: Arc<HittableType>) -> Self {
+        HittableType::Csg(Csg::new(CsgOp::Intersection, left, right))
+    }
+
+    pub fn difference(left: Arc<HittableType>, right: Arc<HittableType>) -> Self {
+        HittableType::Csg(Csg::new(CsgOp::Difference, left, right))
+    }
+
+    // Fog filling `boundary`, use with `MaterialType::Isotropic`
+    pub fn constant_medium(boundary: Arc<HittableType>, density: f64) -> Self {
+        HittableType::ConstantMedium(ConstantMedium::new(boundary, density))
+    }
+
+    // Heterogeneous volume from a voxel grid spanning `min` to `max`
+    pub fn grid_medium(grid: Arc<VoxelGrid>, min: Vec3, max: Vec3, density_scale: f64) -> Self {
+        HittableType::GridMedium(GridMedium::new(grid, min, max, density_scale))
+    }
+
     pub fn compare<'a, 'b>(a: &'a HittableType, b: &'b HittableType, axis: u8) -> Ordering {
         let a_bb = a
             .bounding_box(0.0, 0.0)
@@ -31,73 +118,180 @@ impl HittableType {
     }
 }
 pub trait Hittable {
-    fn hit(&self, mat: MaterialType, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>>;
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB>;
 }
 
 impl Hittable for HittableType {
-    fn hit(&self, mat: MaterialType, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
         match self {
             HittableType::Sphere(s) => s.hit(mat, ray, t_min, t_max),
+            HittableType::Mesh(m) => m.hit(mat, ray, t_min, t_max),
+            HittableType::XYRect(r) => r.hit(mat, ray, t_min, t_max),
+            HittableType::XZRect(r) => r.hit(mat, ray, t_min, t_max),
+            HittableType::YZRect(r) => r.hit(mat, ray, t_min, t_max),
+            HittableType::Box(b) => b.hit(mat, ray, t_min, t_max),
+            HittableType::Plane(p) => p.hit(mat, ray, t_min, t_max),
+            HittableType::Disk(d) => d.hit(mat, ray, t_min, t_max),
+            HittableType::Cylinder(c) => c.hit(mat, ray, t_min, t_max),
+            HittableType::Cone(c) => c.hit(mat, ray, t_min, t_max),
+            HittableType::Torus(t) => t.hit(mat, ray, t_min, t_max),
+            HittableType::Instance(i) => i.hit(mat, ray, t_min, t_max),
+            HittableType::Csg(c) => c.hit(mat, ray, t_min, t_max),
+            HittableType::ConstantMedium(m) => m.hit(mat, ray, t_min, t_max),
+            HittableType::GridMedium(m) => m.hit(mat, ray, t_min, t_max),
         }
     }
 
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
-        match *self {
+        match self {
             HittableType::Sphere(s) => {
-                let r = s.radius();
+                let r = s.radius().abs();
                 let r_vec = Vec3::new(r, r, r);
-                Some(AABB::new(s.center() - r_vec, s.center() - r_vec))
+                Some(AABB::new(s.center() - r_vec, s.center() + r_vec))
             }
+            HittableType::Mesh(m) => Some(m.bounding_box()),
+            HittableType::XYRect(r) => Some(r.bounding_box()),
+            HittableType::XZRect(r) => Some(r.bounding_box()),
+            HittableType::YZRect(r) => Some(r.bounding_box()),
+            HittableType::Box(b) => Some(b.bounding_box()),
+            HittableType::Plane(_) => None,
+            HittableType::Disk(d) => Some(d.bounding_box()),
+            HittableType::Cylinder(c) => Some(c.bounding_box()),
+            HittableType::Cone(c) => Some(c.bounding_box()),
+            HittableType::Torus(t) => Some(t.bounding_box()),
+            HittableType::Instance(i) => i.bounding_box(t0, t1),
+            HittableType::Csg(c) => c.bounding_box(t0, t1),
+            HittableType::ConstantMedium(m) => m.bounding_box(t0, t1),
+            HittableType::GridMedium(m) => Some(m.bounding_box()),
         }
     }
 }
 
+// Objects with their material, and the index of the light they are the
+// surface of if they're sampled as one
 pub struct HittableList {
-    objects: Vec<(Box<HittableType>, MaterialType)>,
+    objects: Vec<(Box<HittableType>, MaterialType, Option<usize>)>,
+    bvh: Option<BVHNode>,
 }
 
 impl HittableList {
     pub fn new() -> Self {
         HittableList {
             objects: Vec::new(),
+            bvh: None,
         }
     }
 
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.bvh = None;
     }
 
     pub fn add(&mut self, object: HittableType, mat: MaterialType) {
-        self.objects.push((Box::new(object), mat))
+        self.objects.push((Box::new(object), mat, None))
+    }
+
+    // Moves every bounded object added so far into a BVH. Unbounded objects like
+    // planes, and objects added afterwards, are still tested linearly.
+    pub fn build_bvh(&mut self, time0: f64, time1: f64) {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = self
+            .objects
+            .drain(..)
+            .partition(|(object, _, _)| object.bounding_box(time0, time1).is_some());
+        self.objects = unbounded;
+
+        let mut list: Vec<(HittableType, MaterialType, Option<usize>)> = bounded
+            .into_iter()
+            .map(|(object, mat, light)| (*object, mat, light))
+            .collect();
+        if let Some(bvh) = self.bvh.take() {
+            bvh.into_leaves(&mut list);
+        }
+
+        if list.len() > 0 {
+            self.bvh = Some(BVHNode::make(&mut list, time0, time1));
+        }
+    }
+
+    // Visits every object with its material and light index, whether in the
+    // BVH or not
+    pub fn for_each_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&HittableType, &MaterialType, &mut Option<usize>),
+    {
+        for (object, mat, light) in &mut self.objects {
+            f(object, mat, light);
+        }
+        if let Some(bvh) = &mut self.bvh {
+            bvh.for_each_mut(&mut f);
+        }
     }
 
     pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut closest_so_far = t_max;
         let mut rec = None;
 
-        for (object, mat) in &self.objects {
-            match object.hit(*mat, ray, t_min, closest_so_far) {
+        for (object, mat, light) in &self.objects {
+            match hit_opaque(object, mat, ray, t_min, closest_so_far) {
                 None => (),
-                Some(temp_rec) => {
+                Some(mut temp_rec) => {
+                    temp_rec.light = *light;
                     closest_so_far = temp_rec.t;
                     rec = Some(temp_rec);
                 }
             }
         }
 
+        if let Some(bvh) = &self.bvh {
+            if let Some(temp_rec) = bvh.hit(ray, t_min, closest_so_far) {
+                rec = Some(temp_rec);
+            }
+        }
+
         return rec;
     }
 
+    // Fraction of light getting along the ray between t_min and t_max: none
+    // if anything solid is in the way, otherwise what's left after the grid
+    // media it passes through, estimated with ratio tracking rather than a
+    // random scattering hit. Grid media inside instances or CSG still block
+    // at random.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        for (object, mat, _) in &self.objects {
+            transmittance *= transmittance_through(object, mat, ray, t_min, t_max);
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+        }
+        if let Some(bvh) = &self.bvh {
+            transmittance *= bvh.transmittance(ray, t_min, t_max);
+        }
+        transmittance
+    }
+
     pub fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
-        if self.objects.len() == 0 {
+        if self.objects.len() == 0 && self.bvh.is_none() {
             return None;
         }
 
-        let bbs: Vec<AABB> = self
+        let mut bbs: Vec<AABB> = self
             .objects
             .iter()
-            .flat_map(|(hittable, _)| match hittable.bounding_box(t0, t1) {
+            .flat_map(|(hittable, _, _)| match hittable.bounding_box(t0, t1) {
                 None => vec![],
                 Some(bb) => vec![bb],
             })
@@ -106,6 +300,9 @@ impl HittableList {
         if bbs.len() < self.objects.len() {
             return None;
         }
+        if let Some(bvh) = &self.bvh {
+            bbs.push(bvh.bounding_box(t0, t1)?);
+        }
 
         let mut output = bbs[0];
         for bb in bbs {
@@ -115,30 +312,65 @@ impl HittableList {
     }
 }
 
+// Hits an object, passing through points where its material is cut out. Every
+// query against the scene goes through here, so shadow rays see the holes too.
+fn hit_opaque<'a>(
+    object: &HittableType,
+    mat: &'a MaterialType,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let mut t_min = t_min;
+    loop {
+        let rec = object.hit(mat, ray, t_min, t_max)?;
+        if !mat.cut_out(&rec) || rec.t <= t_min {
+            return Some(rec);
+        }
+        t_min = rec.t;
+    }
+}
+
+fn transmittance_through(
+    object: &HittableType,
+    mat: &MaterialType,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> f64 {
+    match object {
+        HittableType::GridMedium(medium) => medium.transmittance(ray, t_min, t_max),
+        _ => match hit_opaque(object, mat, ray, t_min, t_max) {
+            Some(_) => 0.0,
+            None => 1.0,
+        },
+    }
+}
+
 enum BVHNode {
     Branch(Box<BVHNode>, Box<BVHNode>, AABB),
-    Leaf(HittableType),
+    Leaf(HittableType, MaterialType, Option<usize>),
 }
 
 impl BVHNode {
     pub fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
-        match *self {
-            BVHNode::Branch(_, _, bb) => Some(bb),
-            BVHNode::Leaf(hittable) => hittable.bounding_box(t0, t1),
+        match self {
+            BVHNode::Branch(_, _, bb) => Some(*bb),
+            BVHNode::Leaf(hittable, _, _) => hittable.bounding_box(t0, t1),
         }
     }
 
-    pub fn hit(&self, mat: MaterialType, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         match self {
             BVHNode::Branch(left, right, bb) => {
                 if !bb.hit(*ray, t_min, t_max) {
                     return None;
                 }
 
-                match left.hit(mat, ray, t_min, t_max) {
-                    None => right.hit(mat, ray, t_min, t_max),
+                match left.hit(ray, t_min, t_max) {
+                    None => right.hit(ray, t_min, t_max),
                     Some(left_rec) => {
-                        match right.hit(mat, ray, t_min, left_rec.t) {
+                        match right.hit(ray, t_min, left_rec.t) {
                             None => Some(left_rec),
                             // Choose the right_rec, because it's closer
                             Some(right_rec) => Some(right_rec),
@@ -146,14 +378,59 @@ impl BVHNode {
                     }
                 }
             }
-            BVHNode::Leaf(hittable) => hittable.hit(mat, ray, t_min, t_max),
+            BVHNode::Leaf(hittable, mat, light) => {
+                let mut rec = hit_opaque(hittable, mat, ray, t_min, t_max)?;
+                rec.light = *light;
+                Some(rec)
+            }
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self {
+            BVHNode::Branch(left, right, bb) => {
+                if !bb.hit(*ray, t_min, t_max) {
+                    return 1.0;
+                }
+                let left = left.transmittance(ray, t_min, t_max);
+                if left <= 0.0 {
+                    return 0.0;
+                }
+                left * right.transmittance(ray, t_min, t_max)
+            }
+            BVHNode::Leaf(hittable, mat, _) => {
+                transmittance_through(hittable, mat, ray, t_min, t_max)
+            }
+        }
+    }
+
+    fn for_each_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&HittableType, &MaterialType, &mut Option<usize>),
+    {
+        match self {
+            BVHNode::Branch(left, right, _) => {
+                left.for_each_mut(f);
+                right.for_each_mut(f);
+            }
+            BVHNode::Leaf(hittable, mat, light) => f(hittable, mat, light),
+        }
+    }
+
+    fn into_leaves(self, out: &mut Vec<(HittableType, MaterialType, Option<usize>)>) {
+        match self {
+            BVHNode::Branch(left, right, _) => {
+                left.into_leaves(out);
+                right.into_leaves(out);
+            }
+            BVHNode::Leaf(hittable, mat, light) => out.push((hittable, mat, light)),
         }
     }
 
     fn new<T: Rng, D: Distribution<u8>>(
         rng: &mut T,
         dist: &D,
-        list: &mut [HittableType],
+        list: &mut [(HittableType, MaterialType, Option<usize>)],
         time0: f64,
         time1: f64,
     ) -> Self {
@@ -164,9 +441,10 @@ impl BVHNode {
         if length < 1 {
             panic!("Called BVHNode::new on empty slice")
         } else if length == 1 {
-            return BVHNode::Leaf(list[0]);
+            let (hittable, mat, light) = &list[0];
+            return BVHNode::Leaf(hittable.clone(), mat.clone(), *light);
         } else {
-            &list.sort_unstable_by(|a, b| HittableType::compare(a, b, axis));
+            &list.sort_unstable_by(|a, b| HittableType::compare(&a.0, &b.0, axis));
             let midpoint = length / 2;
             let (list_left, list_right) = list.split_at_mut(midpoint);
             let left = Self::new(rng, dist, list_left, time0, time1);
@@ -188,9 +466,7 @@ impl BVHNode {
     }
 
     pub fn make(
-        list: &mut Vec<HittableType>,
-        start: usize,
-        end: usize,
+        list: &mut Vec<(HittableType, MaterialType, Option<usize>)>,
         time0: f64,
         time1: f64,
     ) -> Self {
@@ -0,0 +1,479 @@
+use crate::vec::Vec3;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+// A decoded image in linear color, rows stored top to bottom
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "Pixel data does not match image size"
+        );
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Loads a PNG, PPM (P3/P6) or Radiance HDR color image, picked by
+    // extension. HDR files are already linear and may go past 1.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::load_with(path.as_ref(), srgb_to_linear)
+    }
+
+    // Loads an image holding data rather than color (normal or height maps),
+    // so the values are left as stored instead of decoded from sRGB
+    pub fn load_data<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::load_with(path.as_ref(), |c| c)
+    }
+
+    fn load_with(path: &Path, decode: fn(f64) -> f64) -> io::Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        let image = match ext.as_deref() {
+            Some("png") => Self::load_png(path, decode),
+            Some("ppm") => Self::load_ppm(path, decode),
+            Some("hdr") => Self::load_hdr(path),
+            _ => Err(invalid(
+                "Unsupported image format, expected .png, .ppm or .hdr",
+            )),
+        }?;
+        non_empty(image)
+    }
+
+    // Loads the alpha channel of a PNG as a grayscale image, for cutout masks.
+    // Images without alpha are fully opaque.
+    pub fn load_alpha<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let (width, height, channels, buf) = Self::read_png(path.as_ref())?;
+        let pixels = buf
+            .chunks_exact(channels)
+            .take(width * height)
+            .map(|px| {
+                let a = match channels {
+                    2 | 4 => px[channels - 1] as f64 / 255.0,
+                    _ => 1.0,
+                };
+                Vec3::new(a, a, a)
+            })
+            .collect();
+
+        non_empty(Self::new(width, height, pixels))
+    }
+
+    // Decodes a PNG to 8 bit samples, returning width, height and channels
+    fn read_png(path: &Path) -> io::Result<(usize, usize, usize, Vec<u8>)> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info().map_err(|e| invalid(&e.to_string()))?;
+        let mut buf = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut buf)
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::RGB => 3,
+            png::ColorType::RGBA => 4,
+            png::ColorType::Indexed => return Err(invalid("Unexpanded indexed PNG")),
+        };
+
+        Ok((info.width as usize, info.height as usize, channels, buf))
+    }
+
+    fn load_png(path: &Path, decode: fn(f64) -> f64) -> io::Result<Self> {
+        let (width, height, channels, buf) = Self::read_png(path)?;
+        let pixels = buf
+            .chunks_exact(channels)
+            .take(width * height)
+            .map(|px| {
+                let c = |i: usize| decode(px[i] as f64 / 255.0);
+                if channels < 3 {
+                    Vec3::new(c(0), c(0), c(0))
+                } else {
+                    Vec3::new(c(0), c(1), c(2))
+                }
+            })
+            .collect();
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    fn load_ppm(path: &Path, decode: fn(f64) -> f64) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+
+        // Header tokens are whitespace separated, with `#` comments to end of line
+        let mut pos = 0;
+        let mut next_token = |pos: &mut usize| -> io::Result<String> {
+            loop {
+                while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+                    *pos += 1;
+                }
+                if *pos < bytes.len() && bytes[*pos] == b'#' {
+                    while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                        *pos += 1;
+                    }
+                } else {
+                    break;
+                }
+            }
+            let start = *pos;
+            while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            if start == *pos {
+                return Err(invalid("Unexpected end of PPM file"));
+            }
+            Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+        };
+        let number = |s: String| s.parse::<usize>().map_err(|_| invalid("Bad number in PPM"));
+
+        let magic = next_token(&mut pos)?;
+        let width = number(next_token(&mut pos)?)?;
+        let height = number(next_token(&mut pos)?)?;
+        let max_value = number(next_token(&mut pos)?)?;
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid("PPM max value must be between 1 and 65535"));
+        }
+        let max_value = max_value as f64;
+        let count = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(3))
+            .ok_or_else(|| invalid("PPM image is too large"))?;
+
+        let values: Vec<f64> = match magic.as_str() {
+            "P3" => (0..count)
+                .map(|_| next_token(&mut pos).and_then(number).map(|v| v as f64))
+                .collect::<io::Result<_>>()?,
+            "P6" => {
+                // A single whitespace byte separates the header from the
+                // raster, which has two bytes a sample past 255
+                let start = pos + 1;
+                let sample_bytes = if max_value < 256.0 { 1 } else { 2 };
+                let raster = count
+                    .checked_mul(sample_bytes)
+                    .and_then(|length| start.checked_add(length))
+                    .and_then(|end| bytes.get(start..end))
+                    .ok_or_else(|| invalid("Truncated PPM raster"))?;
+                if sample_bytes == 1 {
+                    raster.iter().map(|b| *b as f64).collect()
+                } else {
+                    raster
+                        .chunks_exact(2)
+                        .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64)
+                        .collect()
+                }
+            }
+            _ => return Err(invalid("Expected a P3 or P6 PPM file")),
+        };
+
+        let pixels = values
+            .chunks_exact(3)
+            .map(|c| {
+                Vec3::new(
+                    decode(c[0] / max_value),
+                    decode(c[1] / max_value),
+                    decode(c[2] / max_value),
+                )
+            })
+            .collect();
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    fn load_hdr(path: &Path) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+
+        // Text header lines up to a blank line, then the resolution line
+        let mut pos = 0;
+        let mut next_line = |pos: &mut usize| -> io::Result<String> {
+            let start = *pos;
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+            if *pos >= bytes.len() {
+                return Err(invalid("Unexpected end of HDR header"));
+            }
+            *pos += 1;
+            Ok(String::from_utf8_lossy(&bytes[start..*pos - 1]).into_owned())
+        };
+
+        if !next_line(&mut pos)?.starts_with("#?") {
+            return Err(invalid("Expected a Radiance HDR file"));
+        }
+        loop {
+            let line = next_line(&mut pos)?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid("Only RGBE HDR files are supported"));
+            }
+        }
+        let resolution = next_line(&mut pos)?;
+        let fields: Vec<&str> = resolution.split_whitespace().collect();
+        let (height, width) = match fields.as_slice() {
+            ["-Y", h, "+X", w] => (h.parse::<usize>(), w.parse::<usize>()),
+            _ => return Err(invalid("Unsupported HDR orientation")),
+        };
+        let (height, width) = match (height, width) {
+            (Ok(h), Ok(w)) => (h, w),
+            _ => return Err(invalid("Bad number in HDR resolution")),
+        };
+        if width == 0 || height == 0 {
+            return Err(invalid("HDR image has no pixels"));
+        }
+
+        // Runs pack at most 127 bytes into two, so a raster any bigger than
+        // 64 times what is left of the file can't be there
+        let truncated = || invalid("Truncated HDR raster");
+        let size = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| invalid("HDR image is too large"))?;
+        if size / 64 > bytes.len() - pos {
+            return Err(truncated());
+        }
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut scanline = vec![0u8; width * 4];
+        for _ in 0..height {
+            let header = bytes.get(pos..pos + 4).ok_or_else(truncated)?;
+            let run_length = width >= 8
+                && width < 32768
+                && header[0] == 2
+                && header[1] == 2
+                && ((header[2] as usize) << 8 | header[3] as usize) == width;
+
+            if run_length {
+                // Each channel is stored on its own as runs and literal spans
+                pos += 4;
+                for channel in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = *bytes.get(pos).ok_or_else(truncated)? as usize;
+                        pos += 1;
+                        if count > 128 {
+                            let count = count - 128;
+                            let value = *bytes.get(pos).ok_or_else(truncated)?;
+                            pos += 1;
+                            if x + count > width {
+                                return Err(invalid("Bad HDR run length"));
+                            }
+                            for i in x..x + count {
+                                scanline[i * 4 + channel] = value;
+                            }
+                            x += count;
+                        } else {
+                            if count == 0 || x + count > width {
+                                return Err(invalid("Bad HDR run length"));
+                            }
+                            let span = bytes.get(pos..pos + count).ok_or_else(truncated)?;
+                            for (i, value) in span.iter().enumerate() {
+                                scanline[(x + i) * 4 + channel] = *value;
+                            }
+                            pos += count;
+                            x += count;
+                        }
+                    }
+                }
+            } else {
+                let flat = bytes.get(pos..pos + width * 4).ok_or_else(truncated)?;
+                scanline.copy_from_slice(flat);
+                pos += width * 4;
+            }
+
+            // Three mantissas sharing one exponent
+            pixels.extend(scanline.chunks_exact(4).map(|rgbe| {
+                if rgbe[3] == 0 {
+                    return Vec3::new(0.0, 0.0, 0.0);
+                }
+                let scale = 2f64.powi(rgbe[3] as i32 - 136);
+                Vec3::new(
+                    (rgbe[0] as f64 + 0.5) * scale,
+                    (rgbe[1] as f64 + 0.5) * scale,
+                    (rgbe[2] as f64 + 0.5) * scale,
+                )
+            }));
+        }
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> Vec3 {
+        let x = wrap_coord(x, self.width, wrap);
+        let y = wrap_coord(y, self.height, wrap);
+        self.pixel(x, y)
+    }
+
+    // Looks up the color at texture coordinates (u, v), with v = 0 at the
+    // bottom of the image
+    pub fn sample(&self, u: f64, v: f64, filter: Filter, wrap: Wrap) -> Vec3 {
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+
+        match filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64, wrap),
+            Filter::Bilinear => {
+                // Texel centers sit at half integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = (1.0 - fx) * self.texel(x0, y0, wrap) + fx * self.texel(x0 + 1, y0, wrap);
+                let bottom = (1.0 - fx) * self.texel(x0, y0 + 1, wrap)
+                    + fx * self.texel(x0 + 1, y0 + 1, wrap);
+                (1.0 - fy) * top + fy * bottom
+            }
+        }
+    }
+}
+
+// Wrapping and sampling index into the pixels, so images with none are
+// rejected as they're loaded
+fn non_empty(image: Image) -> io::Result<Image> {
+    if image.width == 0 || image.height == 0 {
+        Err(invalid("Image has no pixels"))
+    } else {
+        Ok(image)
+    }
+}
+
+fn wrap_coord(i: i64, size: usize, wrap: Wrap) -> usize {
+    let n = size as i64;
+    match wrap {
+        Wrap::Repeat => i.rem_euclid(n) as usize,
+        Wrap::Clamp => i.max(0).min(n - 1) as usize,
+        Wrap::Mirror => {
+            let period = i.rem_euclid(2 * n);
+            if period < n {
+                period as usize
+            } else {
+                (2 * n - 1 - period) as usize
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Loads `bytes` as if they were an image file with extension `ext`
+    fn load_bytes(name: &str, ext: &str, bytes: &[u8]) -> io::Result<Image> {
+        let path = std::env::temp_dir().join(format!("raytracer-{}.{}", name, ext));
+        fs::write(&path, bytes).unwrap();
+        let image = Image::load(&path);
+        fs::remove_file(&path).unwrap();
+        image
+    }
+
+    fn hdr(resolution: &str, raster: &[u8]) -> Vec<u8> {
+        let mut bytes =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+        bytes.extend_from_slice(raster);
+        bytes
+    }
+
+    fn assert_invalid(result: io::Result<Image>) {
+        match result {
+            Ok(_) => panic!("Loaded an invalid image"),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+        }
+    }
+
+    #[test]
+    fn hdr_loads_flat_raster() {
+        let image = load_bytes(
+            "flat",
+            "hdr",
+            &hdr("-Y 1 +X 2", &[128, 0, 0, 129, 0, 64, 0, 128]),
+        )
+        .unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert!(image.pixel(0, 0).x() > 0.9);
+        assert!(image.pixel(1, 0).y() > 0.2);
+    }
+
+    #[test]
+    fn hdr_rejects_truncated_raster() {
+        assert_invalid(load_bytes(
+            "truncated",
+            "hdr",
+            &hdr("-Y 2 +X 2", &[1, 2, 3, 4]),
+        ));
+    }
+
+    #[test]
+    fn hdr_rejects_oversized_header() {
+        assert_invalid(load_bytes(
+            "oversized",
+            "hdr",
+            &hdr("-Y 100000 +X 100000", &[0; 64]),
+        ));
+        let huge = format!("-Y {} +X {}", usize::MAX, usize::MAX);
+        assert_invalid(load_bytes("overflowing", "hdr", &hdr(&huge, &[0; 64])));
+    }
+
+    #[test]
+    fn ppm_rejects_bad_headers() {
+        let huge = format!("P6 {} {} 255\n", usize::MAX, usize::MAX);
+        assert_invalid(load_bytes("overflowing", "ppm", huge.as_bytes()));
+        assert_invalid(load_bytes("zero-max", "ppm", b"P6 1 1 0\n\x00\x00\x00"));
+        assert_invalid(load_bytes("big-max", "ppm", b"P3 1 1 70000\n1 2 3"));
+        assert_invalid(load_bytes("short", "ppm", b"P6 2 2 255\n\x00\x00\x00"));
+        assert_invalid(load_bytes("empty", "ppm", b"P3 0 4 255\n"));
+    }
+}
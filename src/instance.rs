@@ -0,0 +1,62 @@
+use crate::{
+    aabb::AABB,
+    hittable::{Hittable, HittableType},
+    materials::{HitRecord, MaterialType},
+    ray::Ray,
+    transform::Transform,
+};
+use std::sync::Arc;
+
+// A transformed reference to a shared hittable. Copies of an instance only
+// copy the transform, the underlying object is shared.
+#[derive(Clone)]
+pub struct Instance {
+    object: Arc<HittableType>,
+    transform: Transform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<HittableType>, transform: Transform) -> Self {
+        Instance { object, transform }
+    }
+
+    pub fn object(&self) -> &Arc<HittableType> {
+        &self.object
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let local_ray = self.transform.inverse_ray(ray);
+
+        self.object
+            .hit(mat, &local_ray, t_min, t_max)
+            .map(|mut rec| {
+                // The inverse transpose preserves the sign of dot(normal, direction),
+                // so front_face computed in object space is still valid.
+                rec.p = self.transform.point(rec.p);
+                rec.normal = self.transform.normal(rec.normal).to_unit();
+                // Tangents go through the matrix itself, which under
+                // non-uniform scale skews them off the transformed normal
+                let tangent = self.transform.vector(rec.tangent);
+                let bitangent = self.transform.vector(rec.bitangent);
+                rec.tangent = tangent.to_unit();
+                rec.bitangent = bitangent.to_unit();
+                rec.with_tangents(tangent, bitangent)
+            })
+    }
+
+    pub fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.object
+            .bounding_box(t0, t1)
+            .map(|bb| self.transform.bounding_box(bb))
+    }
+}
@@ -0,0 +1,199 @@
+use crate::aabb::AABB;
+use crate::environment::find_interval;
+use crate::lights::LightType;
+use crate::rand::Rng;
+use crate::vec::Vec3;
+
+// Picks one of the scene's lights to sample at a shading point, so the cost of
+// direct lighting doesn't grow with the number of lights. Lights at infinity
+// aren't included, they reach every point and are always sampled.
+pub enum LightSampler {
+    Power(Vec<f64>), // Cumulative power of the lights, in order
+    Bvh(Option<LightNode>, Vec<Option<(u64, u32)>>), // Tree, and the branches down to each light
+}
+
+// Lights grouped by where they are, with their total power, so a whole group
+// can be weighed against another by how much it might light a point
+pub enum LightNode {
+    Branch(Box<LightNode>, Box<LightNode>, AABB, f64),
+    Leaf(usize, AABB, f64), // Index into the scene's lights
+}
+
+impl LightSampler {
+    // Picks lights in proportion to their power, wherever the point is
+    pub fn power(lights: &[LightType]) -> Self {
+        let mut cdf = vec![0.0];
+        for light in lights {
+            let power = if light.is_infinite() {
+                0.0
+            } else {
+                light.power()
+            };
+            cdf.push(cdf[cdf.len() - 1] + power);
+        }
+        LightSampler::Power(cdf)
+    }
+
+    // Picks lights by their power and distance from the point, walking down a
+    // tree of them. Scenes with many small lights, most of them far from any
+    // given point, converge much faster this way.
+    pub fn bvh(lights: &[LightType]) -> Self {
+        let mut leaves: Vec<(usize, AABB, f64)> = lights
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| !light.is_infinite() && light.power() > 0.0)
+            .filter_map(|(i, light)| Some((i, light.bounding_box()?, light.power())))
+            .collect();
+
+        let mut trails = vec![None; lights.len()];
+        let root = if leaves.is_empty() {
+            None
+        } else {
+            Some(LightNode::new(&mut leaves, 0, 0, &mut trails))
+        };
+        LightSampler::Bvh(root, trails)
+    }
+
+    // Index of the light to sample and the probability of picking it
+    pub fn sample<T: Rng>(&self, p: Vec3, rng: &mut T) -> Option<(usize, f64)> {
+        match self {
+            LightSampler::Power(cdf) => {
+                let total = cdf[cdf.len() - 1];
+                if total <= 0.0 {
+                    return None;
+                }
+                let i = find_interval(cdf, rng.gen::<f64>() * total);
+                Some((i, (cdf[i + 1] - cdf[i]) / total))
+            }
+            LightSampler::Bvh(root, _) => {
+                let mut node = root.as_ref()?;
+                let mut probability = 1.0;
+                loop {
+                    match node {
+                        LightNode::Leaf(index, _, _) => return Some((*index, probability)),
+                        LightNode::Branch(left, right, _, _) => {
+                            let (l, r) = (left.importance(p), right.importance(p));
+                            if l + r <= 0.0 {
+                                return None;
+                            }
+                            if rng.gen::<f64>() * (l + r) < l {
+                                probability *= l / (l + r);
+                                node = left;
+                            } else {
+                                probability *= r / (l + r);
+                                node = right;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Probability of `sample` picking the light at `index` from `p`
+    pub fn pmf(&self, p: Vec3, index: usize) -> f64 {
+        match self {
+            LightSampler::Power(cdf) => {
+                let total = cdf[cdf.len() - 1];
+                match cdf.get(index + 1) {
+                    Some(upper) if total > 0.0 => (upper - cdf[index]) / total,
+                    _ => 0.0,
+                }
+            }
+            LightSampler::Bvh(root, trails) => {
+                let (mut node, (bits, depth)) = match (root, trails.get(index)) {
+                    (Some(root), Some(Some(trail))) => (root, *trail),
+                    _ => return 0.0,
+                };
+                let mut probability = 1.0;
+                for level in 0..depth {
+                    if let LightNode::Branch(left, right, _, _) = node {
+                        let (l, r) = (left.importance(p), right.importance(p));
+                        if l + r <= 0.0 {
+                            return 0.0;
+                        }
+                        if bits >> level & 1 == 0 {
+                            probability *= l / (l + r);
+                            node = left;
+                        } else {
+                            probability *= r / (l + r);
+                            node = right;
+                        }
+                    }
+                }
+                probability
+            }
+        }
+    }
+}
+
+impl LightNode {
+    // Splits the lights in half along the axis they're most spread out on.
+    // `bits` and `depth` record the branches taken to get here, for `pmf`,
+    // and halving keeps the tree far shallower than the 64 bits can hold.
+    fn new(
+        leaves: &mut [(usize, AABB, f64)],
+        bits: u64,
+        depth: u32,
+        trails: &mut Vec<Option<(u64, u32)>>,
+    ) -> Self {
+        if leaves.len() == 1 {
+            let (index, bounds, power) = leaves[0];
+            trails[index] = Some((bits, depth));
+            return LightNode::Leaf(index, bounds, power);
+        }
+
+        let centroid = |b: &AABB| 0.5 * (b.min() + b.max());
+        let mut lo = centroid(&leaves[0].1);
+        let mut hi = lo;
+        for (_, bounds, _) in leaves.iter() {
+            let c = centroid(bounds);
+            lo = Vec3::new(lo.x().min(c.x()), lo.y().min(c.y()), lo.z().min(c.z()));
+            hi = Vec3::new(hi.x().max(c.x()), hi.y().max(c.y()), hi.z().max(c.z()));
+        }
+        let extent = hi - lo;
+        let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            0
+        } else if extent.y() >= extent.z() {
+            1
+        } else {
+            2
+        };
+        leaves.sort_unstable_by(|a, b| {
+            centroid(&a.1)
+                .comp(axis)
+                .partial_cmp(&centroid(&b.1).comp(axis))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let (left, right) = leaves.split_at_mut(leaves.len() / 2);
+        let left = LightNode::new(left, bits, depth + 1, trails);
+        let right = LightNode::new(right, bits | 1 << depth, depth + 1, trails);
+        let bounds = AABB::surrounding_box(left.bounds(), right.bounds());
+        let power = left.power() + right.power();
+        LightNode::Branch(Box::new(left), Box::new(right), bounds, power)
+    }
+
+    fn bounds(&self) -> AABB {
+        match self {
+            LightNode::Branch(_, _, bounds, _) | LightNode::Leaf(_, bounds, _) => *bounds,
+        }
+    }
+
+    fn power(&self) -> f64 {
+        match self {
+            LightNode::Branch(_, _, _, power) | LightNode::Leaf(_, _, power) => *power,
+        }
+    }
+
+    // How much the lights below might light `p`. Their power falls off with
+    // the square of the distance, but no faster than from the edge of the
+    // group, so points close to or inside it still weigh every light.
+    fn importance(&self, p: Vec3) -> f64 {
+        let bounds = self.bounds();
+        let center = 0.5 * (bounds.min() + bounds.max());
+        let half_diagonal = 0.5 * (bounds.max() - bounds.min()).length();
+        let d2 = (center - p).length_squared();
+        self.power() / d2.max(half_diagonal * half_diagonal).max(1e-8)
+    }
+}
@@ -0,0 +1,277 @@
+use crate::aabb::AABB;
+use crate::rand::Rng;
+use crate::vec::Vec3;
+use std::f64::consts;
+
+// Lights sampled directly at each bounce. Most have no surface and are only
+// seen this way, spheres are emissive objects in the scene that bounced rays
+// can find too.
+#[derive(Clone)]
+pub enum LightType {
+    Point(Vec3, Vec3),                // Position, Intensity
+    Spot(Vec3, Vec3, Vec3, f64, f64), // Position, Axis, Intensity, Inner and outer cone cosines
+    Directional(Vec3, Vec3, f64),     // Travel direction, Irradiance, Cosine of angular radius
+    Sphere(Vec3, f64, Vec3),          // Center, Radius, Radiance of an emissive sphere
+}
+
+// A direction to sample a light along and the light arriving from it, already
+// divided by the probability of picking the direction
+pub struct LightSample {
+    pub direction: Vec3, // Unit vector toward the light
+    pub distance: f64,   // Infinite for directional lights
+    pub radiance: Vec3,
+}
+
+// A ray leaving a light, to trace a path of light out into the scene from
+pub struct Emission {
+    pub origin: Vec3,
+    pub direction: Vec3,      // Unit vector
+    pub normal: Option<Vec3>, // For lights with a surface
+    pub radiance: Vec3,
+    pub pdf_position: f64,  // Over the light's area, 1 for lights at a point
+    pub pdf_direction: f64, // Over solid angle
+}
+
+impl LightType {
+    pub fn point(position: Vec3, intensity: Vec3) -> Self {
+        LightType::Point(position, intensity)
+    }
+
+    // Full intensity inside `inner` degrees of the axis, fading out to nothing
+    // at `outer` degrees
+    pub fn spot(position: Vec3, direction: Vec3, intensity: Vec3, inner: f64, outer: f64) -> Self {
+        LightType::Spot(
+            position,
+            direction.to_unit(),
+            intensity,
+            inner.to_radians().cos(),
+            outer.to_radians().cos(),
+        )
+    }
+
+    // Light from a distant disk like the sun, `angular_diameter` degrees
+    // across. Zero gives perfectly sharp shadows.
+    pub fn directional(direction: Vec3, irradiance: Vec3, angular_diameter: f64) -> Self {
+        LightType::Directional(
+            direction.to_unit(),
+            irradiance,
+            (angular_diameter / 2.0).to_radians().cos(),
+        )
+    }
+
+    // Lights so far away that they reach everywhere, rather than one place
+    pub fn is_infinite(&self) -> bool {
+        matches!(self, LightType::Directional(..))
+    }
+
+    // Lights at a single point, or from a single direction, which paths can
+    // only reach by sampling them
+    pub fn is_delta(&self) -> bool {
+        !matches!(self, LightType::Sphere(..))
+    }
+
+    // Rough total power given off, for picking between lights
+    pub fn power(&self) -> f64 {
+        match self {
+            LightType::Point(_, intensity) => 4.0 * consts::PI * intensity.luminance(),
+            LightType::Spot(_, _, intensity, cos_inner, cos_outer) => {
+                2.0 * consts::PI * (1.0 - 0.5 * (cos_inner + cos_outer)) * intensity.luminance()
+            }
+            LightType::Directional(_, irradiance, _) => irradiance.luminance(),
+            LightType::Sphere(_, radius, radiance) => {
+                4.0 * consts::PI * consts::PI * radius * radius * radiance.luminance()
+            }
+        }
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        match self {
+            LightType::Point(position, _) | LightType::Spot(position, ..) => {
+                Some(AABB::new(*position, *position))
+            }
+            LightType::Directional(..) => None,
+            LightType::Sphere(center, radius, _) => {
+                let r = Vec3::new(*radius, *radius, *radius);
+                Some(AABB::new(*center - r, *center + r))
+            }
+        }
+    }
+
+    // Density over solid angle of `sample` picking `direction` from `p`. Zero
+    // for lights that rays can't hit.
+    pub fn pdf(&self, p: Vec3, direction: Vec3) -> f64 {
+        match self {
+            LightType::Sphere(center, radius, _) => match cone_to_sphere(p, *center, *radius) {
+                Some((axis, cos_max)) if Vec3::dot(direction.to_unit(), axis) >= cos_max => {
+                    1.0 / (2.0 * consts::PI * (1.0 - cos_max))
+                }
+                _ => 0.0,
+            },
+            _ => 0.0,
+        }
+    }
+
+    // Picks a ray for light to leave along. Lights at infinity have no place
+    // to leave from and aren't traced.
+    pub fn sample_emission<T: Rng>(&self, rng: &mut T) -> Option<Emission> {
+        match self {
+            LightType::Point(position, intensity) => Some(Emission {
+                origin: *position,
+                direction: Vec3::random_unit_vector(rng),
+                normal: None,
+                radiance: *intensity,
+                pdf_position: 1.0,
+                pdf_direction: 1.0 / (4.0 * consts::PI),
+            }),
+            LightType::Spot(position, axis, intensity, cos_inner, cos_outer) => {
+                let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_outer);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * consts::PI * rng.gen::<f64>();
+                let (s, t) = Vec3::basis(*axis);
+                Some(Emission {
+                    origin: *position,
+                    direction: sin_theta * phi.cos() * s
+                        + sin_theta * phi.sin() * t
+                        + cos_theta * *axis,
+                    normal: None,
+                    radiance: smoothstep(*cos_outer, *cos_inner, cos_theta) * *intensity,
+                    pdf_position: 1.0,
+                    pdf_direction: 1.0 / (2.0 * consts::PI * (1.0 - cos_outer)),
+                })
+            }
+            LightType::Directional(..) => None,
+            LightType::Sphere(center, radius, radiance) => {
+                // Uniform over the surface, then cosine weighted about the normal
+                let normal = Vec3::random_unit_vector(rng);
+                let r2: f64 = rng.gen();
+                let phi = 2.0 * consts::PI * rng.gen::<f64>();
+                let (s, t) = Vec3::basis(normal);
+                let cos_theta = (1.0 - r2).sqrt();
+                let direction = r2.sqrt() * (phi.cos() * s + phi.sin() * t) + cos_theta * normal;
+                Some(Emission {
+                    origin: *center + *radius * normal,
+                    direction,
+                    normal: Some(normal),
+                    radiance: *radiance,
+                    pdf_position: 1.0 / (4.0 * consts::PI * radius * radius),
+                    pdf_direction: cos_theta / consts::PI,
+                })
+            }
+        }
+    }
+
+    // Densities of `sample_emission` picking the point `p` on the light and
+    // `direction` from it
+    pub fn pdf_emission(&self, p: Vec3, direction: Vec3) -> (f64, f64) {
+        match self {
+            LightType::Point(..) => (1.0, 1.0 / (4.0 * consts::PI)),
+            LightType::Spot(_, axis, _, _, cos_outer) => {
+                if Vec3::dot(direction.to_unit(), *axis) > *cos_outer {
+                    (1.0, 1.0 / (2.0 * consts::PI * (1.0 - cos_outer)))
+                } else {
+                    (1.0, 0.0)
+                }
+            }
+            LightType::Directional(..) => (0.0, 0.0),
+            LightType::Sphere(center, radius, _) => {
+                let normal = (p - *center) / *radius;
+                let cos_theta = Vec3::dot(direction.to_unit(), normal).max(0.0);
+                (
+                    1.0 / (4.0 * consts::PI * radius * radius),
+                    cos_theta / consts::PI,
+                )
+            }
+        }
+    }
+
+    pub fn sample<T: Rng>(&self, p: Vec3, rng: &mut T) -> Option<LightSample> {
+        match self {
+            LightType::Point(position, intensity) => {
+                let to_light = *position - p;
+                let distance = to_light.length();
+                Some(LightSample {
+                    direction: to_light / distance,
+                    distance,
+                    radiance: *intensity / (distance * distance),
+                })
+            }
+            LightType::Spot(position, axis, intensity, cos_inner, cos_outer) => {
+                let to_light = *position - p;
+                let distance = to_light.length();
+                let direction = to_light / distance;
+                let cosine = Vec3::dot(-direction, *axis);
+                if cosine <= *cos_outer {
+                    return None;
+                }
+                let falloff = smoothstep(*cos_outer, *cos_inner, cosine);
+                Some(LightSample {
+                    direction,
+                    distance,
+                    radiance: falloff * *intensity / (distance * distance),
+                })
+            }
+            LightType::Directional(travel, irradiance, cos_max) => {
+                // Uniform over the cone the disk covers. The radiance is the
+                // irradiance spread over the cone's solid angle, which cancels
+                // with the pdf.
+                let toward = -*travel;
+                let direction = if *cos_max >= 1.0 {
+                    toward
+                } else {
+                    let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+                    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                    let phi = 2.0 * consts::PI * rng.gen::<f64>();
+                    let (s, t) = Vec3::basis(toward);
+                    sin_theta * phi.cos() * s + sin_theta * phi.sin() * t + cos_theta * toward
+                };
+                Some(LightSample {
+                    direction,
+                    distance: f64::INFINITY,
+                    radiance: *irradiance,
+                })
+            }
+            LightType::Sphere(center, radius, radiance) => {
+                // Uniform over the cone of directions the sphere covers, with
+                // nothing to sample from inside it
+                let (axis, cos_max) = cone_to_sphere(p, *center, *radius)?;
+                let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * consts::PI * rng.gen::<f64>();
+                let (s, t) = Vec3::basis(axis);
+                let direction =
+                    sin_theta * phi.cos() * s + sin_theta * phi.sin() * t + cos_theta * axis;
+
+                // Nearest point of the sphere along the direction, stopping
+                // just short so shadow rays don't hit the light itself
+                let d = (*center - p).length();
+                let offset = (radius * radius - d * d * sin_theta * sin_theta).max(0.0);
+                let distance = (d * cos_theta - offset.sqrt()) * (1.0 - 1e-6);
+                Some(LightSample {
+                    direction,
+                    distance,
+                    radiance: 2.0 * consts::PI * (1.0 - cos_max) * *radiance,
+                })
+            }
+        }
+    }
+}
+
+// Axis and cosine of the half angle of the cone a sphere covers seen from `p`,
+// or None if `p` is inside it
+fn cone_to_sphere(p: Vec3, center: Vec3, radius: f64) -> Option<(Vec3, f64)> {
+    let to_center = center - p;
+    let d2 = to_center.length_squared();
+    if d2 <= radius * radius {
+        return None;
+    }
+    let cos_max = (1.0 - radius * radius / d2).max(0.0).sqrt();
+    Some((to_center / d2.sqrt(), cos_max))
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    if edge1 <= edge0 {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
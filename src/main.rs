@@ -11,47 +11,212 @@ use rayon::prelude::*;
 use std::time::Instant;
 
 mod aabb;
+mod background;
+mod bdpt;
 mod camera;
 mod color;
+mod csg;
+mod environment;
 mod hittable;
+mod image;
+mod instance;
+mod light_sampler;
+mod lights;
 mod materials;
+mod medium;
+mod mesh;
+mod microfacet;
+mod nested;
+mod perlin;
+mod photon;
 mod pixmap;
+mod principled;
 mod ray;
 mod scenes;
+mod sky;
 mod solids;
+mod spectrum;
 mod texture;
+mod transform;
 mod vec;
+mod world;
 
+use bdpt::SplatFilm;
 use camera::{Camera, CameraOptions};
 use color::{Color, Samples};
-use hittable::HittableList;
-use materials::{Material, MaterialType};
+use materials::{HitRecord, Material, MaterialType};
+use medium::free_flight;
+use nested::MediumStack;
+use photon::PhotonMap;
 use pixmap::PixMap;
 use ray::Ray;
 use scenes::{use_scene, Scene};
 use solids::Sphere;
+use spectrum::SpectralFilter;
 use texture::TextureType;
 use vec::Vec3;
+use world::World;
 
-fn ray_color<T: Rng>(ray: &Ray, world: &HittableList, rng: &mut T, depth: u16) -> Vec3 {
-    if depth <= 0 {
+fn ray_color<'a, T: Rng>(
+    ray: &Ray,
+    world: &'a World,
+    media: &mut MediumStack<'a>,
+    rng: &mut T,
+    depth: u16,
+    bounce: Option<(f64, Vec3)>, // Pdf and point of the last bounce if lights were sampled there
+    caustic: bool,               // Only mirror-like bounces since a diffuse one, as photons take
+) -> Vec3 {
+    if depth == 0 {
         return Vec3::new(0.0, 0.0, 0.0);
     }
 
     match world.hit(ray, 0.001, f64::INFINITY) {
         None => {
-            // Show gradient for background
-            let unit_direction = ray.direction().to_unit();
-            let t = 0.5 * (unit_direction.y() + 1.0);
-            let lerped = (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
-            return lerped;
+            let background = world.background();
+            let color = background.color(ray.direction());
+            match bounce {
+                Some((pdf, _)) => color * power_heuristic(pdf, background.pdf(ray.direction())),
+                None => color,
+            }
+        }
+        Some(mut rec) => {
+            // Inside a medium light is absorbed, and may scatter before it
+            // reaches the surface
+            let mut transmittance = Vec3::new(1.0, 1.0, 1.0);
+            if let Some((sigma_s, sigma_a)) = media.interior() {
+                let length = ray.direction().length();
+                let (scatter_at, weight) = free_flight(sigma_s, sigma_a, rec.t * length, rng);
+                if let Some(distance) = scatter_at {
+                    let p = ray.at(distance / length);
+                    let scattered = Ray::new(p, Vec3::random_unit_vector(rng))
+                        .with_wavelength(ray.wavelength());
+                    return weight
+                        * ray_color(&scattered, world, media, rng, depth - 1, None, false);
+                }
+                transmittance = weight;
+            }
+
+            match media.outside_ior(&rec, ray.wavelength()) {
+                Some(ior) => rec.outside_ior = ior,
+                None => {
+                    // Surface hidden inside a higher priority medium. The
+                    // ray carries on from the same bounce, so a light it
+                    // finds is weighed from there.
+                    media.cross(rec.mat, rec.front_face);
+                    let through =
+                        Ray::new(rec.p, ray.direction()).with_wavelength(ray.wavelength());
+                    return transmittance
+                        * ray_color(&through, world, media, rng, depth - 1, bounce, caustic);
+                }
+            }
+
+            let mut emitted = rec.mat.emitted(&rec);
+            if let Some((pdf, from)) = bounce {
+                // Sampled directly at the last bounce as well
+                let light_pdf = world.light_pdf(from, &rec, ray.direction());
+                emitted = emitted * power_heuristic(pdf, light_pdf);
+            }
+            let mut direct = direct_light(ray, &rec, world, rng);
+            if let Some(caustics) = world.caustics() {
+                // The photons already brought this light here
+                if caustic && world.emitter(&rec).is_some() {
+                    emitted = Vec3::new(0.0, 0.0, 0.0);
+                }
+                direct = direct + caustics.radiance(ray, &rec);
+            }
+            match rec.mat.scatter(ray, &rec, rng) {
+                None => transmittance * (emitted + direct),
+                Some((attentuation, scattered)) => {
+                    // Materials build fresh rays, the path keeps its wavelength
+                    let scattered = scattered.with_wavelength(ray.wavelength());
+                    let outward = Vec3::dot(scattered.direction(), rec.outward_normal()) > 0.0;
+                    if outward != rec.front_face {
+                        media.cross(rec.mat, rec.front_face);
+                    }
+                    let eval = rec.mat.eval(ray, &rec, scattered.direction());
+                    // Mirror-like bounces right after a diffuse one, or after
+                    // more of them, are how photons reach diffuse surfaces.
+                    // Lights that shoot photons are sampled, so a diffuse
+                    // bounce always leaves its pdf.
+                    let caustic = eval.is_none() && (caustic || bounce.is_some());
+                    let bounce = if world.has_sampled_emitters() {
+                        eval.map(|(_, pdf)| (pdf, rec.p))
+                    } else {
+                        None
+                    };
+                    let indirect = attentuation
+                        * ray_color(&scattered, world, media, rng, depth - 1, bounce, caustic);
+                    transmittance * (emitted + direct + indirect)
+                }
+            }
+        }
+    }
+}
+
+// Light reaching the hit straight from the scene's lights, reflected back
+// along the ray
+fn direct_light<T: Rng>(ray: &Ray, rec: &HitRecord, world: &World, rng: &mut T) -> Vec3 {
+    let mut total = Vec3::new(0.0, 0.0, 0.0);
+    // Every light at infinity, and one of the rest picked by how much it's
+    // likely to contribute
+    let infinite = world.lights().iter().filter(|light| light.is_infinite());
+    let picked = world.sample_light(rec.p, rng);
+    for (light, probability) in infinite.map(|light| (light, 1.0)).chain(picked) {
+        let sample = match light.sample(rec.p, rng) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let (f, bsdf_pdf) = match rec.mat.eval(ray, rec, sample.direction) {
+            Some(eval) => eval,
+            // Mirror-like surfaces only find lights by bouncing
+            None => return total,
+        };
+        if f == Vec3::new(0.0, 0.0, 0.0) {
+            continue;
+        }
+
+        let shadow = Ray::new(rec.p, sample.direction).with_wavelength(ray.wavelength());
+        let visibility = world.transmittance(&shadow, sample.distance);
+        if visibility > 0.0 {
+            // Lights with a surface can be found by bouncing too
+            let light_pdf = probability * light.pdf(rec.p, sample.direction);
+            let weight = if light_pdf > 0.0 {
+                power_heuristic(light_pdf, bsdf_pdf)
+            } else {
+                1.0
+            };
+            total = total + visibility * weight / probability * f * sample.radiance;
         }
-        Some(rec) => match rec.mat.scatter(ray, &rec, rng) {
-            None => Vec3::new(0.0, 0.0, 0.0),
-            Some((attentuation, scattered)) => {
-                attentuation * ray_color(&scattered, world, rng, depth - 1)
+    }
+
+    // Bright parts of the background, weighted against bounced rays finding
+    // them on their own
+    let background = world.background();
+    if let Some((direction, light_pdf)) = background.sample(rng) {
+        if let Some((f, bsdf_pdf)) = rec.mat.eval(ray, rec, direction) {
+            let shadow = Ray::new(rec.p, direction).with_wavelength(ray.wavelength());
+            let visibility = if f == Vec3::new(0.0, 0.0, 0.0) {
+                0.0
+            } else {
+                world.transmittance(&shadow, f64::INFINITY)
+            };
+            if visibility > 0.0 {
+                let weight = visibility * power_heuristic(light_pdf, bsdf_pdf) / light_pdf;
+                total = total + weight * f * background.color(direction);
             }
-        },
+        }
+    }
+    total
+}
+
+// Weight for a sample from a strategy with density `pdf`, when another with
+// density `other` could also have produced it
+fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other * other);
+    if b == 0.0 {
+        1.0
+    } else {
+        a / (a + b)
     }
 }
 
@@ -65,6 +230,15 @@ const SAMPLES_PER_PIXEL: u16 = 5;
 const IMAGE_WIDTH: u16 = 800;
 const IMAGE_HEIGHT: u16 = 600;
 const MAX_DEPTH: u16 = 1000;
+// Trace each sample at a single wavelength, so dispersive glass splits light
+const SPECTRAL: bool = false;
+// Trace paths from the lights as well as the camera and join them, which finds
+// caustics the camera alone almost never does
+const BIDIRECTIONAL: bool = false;
+// Shoot photons through glass and off mirrors before rendering, and look up
+// the caustics they make instead of waiting for paths to find them
+const PHOTON_MAP: bool = false;
+const CAUSTIC_PHOTONS: usize = 200_000;
 
 #[derive(Clone, Copy)]
 struct Pixel {
@@ -76,8 +250,18 @@ fn main() {
     let mut pm = PixMap::new(IMAGE_WIDTH, IMAGE_HEIGHT);
     let mut pixels = Vec::new();
 
-    let world = use_scene(Scene::SHINY);
+    let mut world = use_scene(Scene::SHINY);
+    world.build_bvh(0.0, 0.0);
+    if PHOTON_MAP {
+        let caustics = PhotonMap::caustics(&world, CAUSTIC_PHOTONS, &mut thread_rng());
+        world.set_caustics(caustics);
+    }
     let cam = Camera::new(CameraOptions::cool2(&pm));
+    let spectral = if SPECTRAL {
+        Some(SpectralFilter::new())
+    } else {
+        None
+    };
 
     let start = Instant::now();
 
@@ -97,18 +281,21 @@ fn main() {
             .template("[{elapsed_precise}] {wide_bar} {pos:>7}/{len:7} {msg} [{eta_precise}]"),
     );
 
-    let colors: Vec<Color> = pixels
-        .into_par_iter()
+    let film = SplatFilm::new(pm.width() as usize, pm.height() as usize);
+    let samples: Vec<Samples> = pixels
+        .par_iter()
         .progress_with(pb)
         .map(|p| {
             let mut rng = thread_rng();
-            let color = raytrace_pixel(p as Pixel, &pm, cam, &world, &mut rng);
-            return color;
+            raytrace_pixel(*p, &pm, cam, &world, spectral.as_ref(), &film, &mut rng)
         })
         .collect();
 
-    for (i, color) in colors.iter().enumerate() {
-        pm.push(*color);
+    // Light paths land on any pixel, so their light is only added once every
+    // pixel is done
+    for (p, mut samples) in pixels.iter().zip(samples) {
+        samples.add_splat(film.get(p.x as usize, p.y as usize));
+        pm.push(samples.into());
     }
     let duration = start.elapsed();
 
@@ -123,9 +310,11 @@ fn raytrace_pixel<T: Rng>(
     p: Pixel,
     pm: &PixMap,
     camera: Camera,
-    world: &HittableList,
+    world: &World,
+    spectral: Option<&SpectralFilter>,
+    film: &SplatFilm,
     rng: &mut T,
-) -> Color {
+) -> Samples {
     let mut samples = Samples::default();
     let i = p.x as f64;
     let j = p.y as f64;
@@ -134,7 +323,27 @@ fn raytrace_pixel<T: Rng>(
         let u = (i + rng.gen::<f64>()) / pm.width() as f64;
         let v = (j + rng.gen::<f64>()) / pm.height() as f64;
         let r = camera.get_ray(u, v, rng);
-        samples.add_sample(ray_color(&r, world, rng, MAX_DEPTH));
+        let (r, weight) = match spectral {
+            None => (r, Vec3::new(1.0, 1.0, 1.0)),
+            Some(filter) => {
+                let lambda = filter.sample_wavelength(rng);
+                (r.with_wavelength(Some(lambda)), filter.weight(lambda))
+            }
+        };
+        let sample = if BIDIRECTIONAL {
+            bdpt::trace(&r, &camera, world, film, weight, rng)
+        } else {
+            ray_color(
+                &r,
+                world,
+                &mut MediumStack::new(),
+                rng,
+                MAX_DEPTH,
+                None,
+                false,
+            )
+        };
+        samples.add_sample(sample * weight);
     }
-    return samples.into();
+    return samples;
 }
@@ -1,17 +1,281 @@
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, Frame, Ggx};
+use crate::principled::Principled;
 use crate::rand::Rng;
 use crate::ray::Ray;
+use crate::spectrum::Ior;
 use crate::texture::{Texture, TextureType};
 use crate::vec::Vec3;
+use std::f64::consts;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum MaterialType {
-    Lambertian(TextureType), // Albedo
-    Metal(Vec3, f64),        // Albedo, Fuzz radius
-    Dielectric(f64),         // Refractive Index
+    Lambertian(TextureType),                              // Albedo
+    Metal(TextureType, TextureType),                      // Albedo, Fuzz radius
+    Dielectric(Ior, TextureType, Vec3),                   // Refractive Index, Tint, Absorption
+    Isotropic(TextureType),                               // Albedo, scatters uniformly
+    Conductor(Vec3, Vec3, TextureType),                   // IOR, Extinction, Roughness
+    RoughDielectric(Ior, TextureType, TextureType, Vec3), // IOR, Roughness, Tint, Absorption
+    Principled(Box<Principled>),                          // Layered uber-material
+    Bump(Box<MaterialType>, TextureType, f64),            // Base, Height, Strength
+    NormalMap(Box<MaterialType>, TextureType),            // Base, Tangent space normals
+    Priority(Box<MaterialType>, u32),                     // Base, Priority where media overlap
+    Subsurface(Ior, Vec3, Vec3),                          // IOR, Scattering, Absorption
+    Cutout(Box<MaterialType>, TextureType, f64),          // Base, Opacity, Threshold
+    TwoSided(Box<MaterialType>, Box<MaterialType>),       // Front, Back
+    ThinDielectric(Ior, TextureType),                     // Refractive Index, Tint
+    DiffuseLight(TextureType),                            // Emitted radiance
+}
+
+impl MaterialType {
+    pub fn lambertian(albedo: Vec3) -> Self {
+        MaterialType::Lambertian(TextureType::Constant(albedo))
+    }
+
+    pub fn metal(albedo: Vec3, fuzz: f64) -> Self {
+        MaterialType::Metal(
+            TextureType::Constant(albedo),
+            TextureType::Constant(Vec3::new(fuzz, fuzz, fuzz)),
+        )
+    }
+
+    pub fn dielectric<I: Into<Ior>>(ior: I) -> Self {
+        MaterialType::Dielectric(
+            ior.into(),
+            TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+            Vec3::new(0.0, 0.0, 0.0),
+        )
+    }
+
+    // Glass that absorbs light inside it, so that light traveling `distance`
+    // through it is left with `color`. Thick parts come out darker.
+    pub fn colored_glass<I: Into<Ior>>(ior: I, color: Vec3, distance: f64) -> Self {
+        MaterialType::Dielectric(
+            ior.into(),
+            TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+            absorption_for(color, distance),
+        )
+    }
+
+    pub fn conductor(eta: Vec3, k: Vec3, roughness: f64) -> Self {
+        MaterialType::Conductor(
+            eta,
+            k,
+            TextureType::Constant(Vec3::new(1.0, 1.0, 1.0) * roughness),
+        )
+    }
+
+    // Complex refractive indices sampled at roughly 650, 550 and 450nm
+    pub fn gold(roughness: f64) -> Self {
+        Self::conductor(
+            Vec3::new(0.143, 0.374, 1.442),
+            Vec3::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::conductor(
+            Vec3::new(0.200, 0.924, 1.102),
+            Vec3::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::conductor(
+            Vec3::new(1.657, 0.880, 0.521),
+            Vec3::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    // Translucent material lit by a random walk through its interior. `color`
+    // is roughly the color of a thick, diffusely lit slab and light travels
+    // `mean_free_path` on average between scattering events. The inversion
+    // from color to single scattering albedo is from Chiang et al. (2016).
+    pub fn subsurface<I: Into<Ior>>(ior: I, color: Vec3, mean_free_path: f64) -> Self {
+        let albedo = |a: f64| {
+            let a = a.max(0.0).min(1.0);
+            let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            1.0 - s * s
+        };
+        let albedo = Vec3::new(albedo(color.x()), albedo(color.y()), albedo(color.z()));
+        let sigma_t = 1.0 / mean_free_path;
+        MaterialType::Subsurface(
+            ior.into(),
+            sigma_t * albedo,
+            sigma_t * (Vec3::new(1.0, 1.0, 1.0) - albedo),
+        )
+    }
+
+    pub fn rough_dielectric<I: Into<Ior>>(ior: I, roughness: f64) -> Self {
+        MaterialType::RoughDielectric(
+            ior.into(),
+            TextureType::Constant(Vec3::new(1.0, 1.0, 1.0) * roughness),
+            TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+            Vec3::new(0.0, 0.0, 0.0),
+        )
+    }
+
+    pub fn principled(params: Principled) -> Self {
+        MaterialType::Principled(Box::new(params))
+    }
+
+    // Scattering and absorption coefficients of the medium enclosed by
+    // surfaces of this material, per unit distance. Two-sided surfaces use the
+    // side that was hit.
+    pub fn interior(&self, front_face: bool) -> Option<(Vec3, Vec3)> {
+        let clear = Vec3::new(0.0, 0.0, 0.0);
+        match self {
+            MaterialType::Dielectric(_, _, sigma_a)
+            | MaterialType::RoughDielectric(_, _, _, sigma_a) => Some((clear, *sigma_a)),
+            MaterialType::Subsurface(_, sigma_s, sigma_a) => Some((*sigma_s, *sigma_a)),
+            MaterialType::Bump(base, _, _)
+            | MaterialType::NormalMap(base, _)
+            | MaterialType::Priority(base, _)
+            | MaterialType::Cutout(base, _, _) => base.interior(front_face),
+            MaterialType::TwoSided(front, back) => {
+                if front_face {
+                    front.interior(front_face)
+                } else {
+                    back.interior(front_face)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Refractive index of the medium enclosed by surfaces of this material,
+    // None for surfaces that don't bound a medium. Two-sided surfaces use the
+    // side that was hit.
+    pub fn ior(&self, wavelength: Option<f64>, front_face: bool) -> Option<f64> {
+        match self {
+            MaterialType::Dielectric(ior, _, _)
+            | MaterialType::RoughDielectric(ior, _, _, _)
+            | MaterialType::Subsurface(ior, _, _) => Some(ior.at(wavelength)),
+            MaterialType::Bump(base, _, _)
+            | MaterialType::NormalMap(base, _)
+            | MaterialType::Priority(base, _)
+            | MaterialType::Cutout(base, _, _) => base.ior(wavelength, front_face),
+            MaterialType::TwoSided(front, back) => {
+                if front_face {
+                    front.ior(wavelength, front_face)
+                } else {
+                    back.ior(wavelength, front_face)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn priority(&self) -> u32 {
+        match self {
+            MaterialType::Priority(_, priority) => *priority,
+            _ => 0,
+        }
+    }
+
+    pub fn diffuse_light(radiance: Vec3) -> Self {
+        MaterialType::DiffuseLight(TextureType::Constant(radiance))
+    }
+
+    pub fn two_sided(front: MaterialType, back: MaterialType) -> Self {
+        MaterialType::TwoSided(Box::new(front), Box::new(back))
+    }
+
+    // An infinitely thin pane or film, light passes straight through it
+    pub fn thin_dielectric<I: Into<Ior>>(ior: I) -> Self {
+        MaterialType::ThinDielectric(ior.into(), TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    // Whether the surface is cut away at the hit, so rays pass through it
+    pub fn cut_out(&self, rec: &HitRecord) -> bool {
+        match self {
+            MaterialType::Cutout(base, opacity, threshold) => {
+                opacity.scalar(rec.u, rec.v, &rec.p) < *threshold || base.cut_out(rec)
+            }
+            MaterialType::Bump(base, _, _)
+            | MaterialType::NormalMap(base, _)
+            | MaterialType::Priority(base, _) => base.cut_out(rec),
+            MaterialType::TwoSided(front, back) => {
+                if rec.front_face {
+                    front.cut_out(rec)
+                } else {
+                    back.cut_out(rec)
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // Cuts holes where the scalar value of `opacity` is below one half
+    pub fn cutout(base: MaterialType, opacity: TextureType) -> Self {
+        MaterialType::Cutout(Box::new(base), opacity, 0.5)
+    }
+
+    pub fn with_priority(self, priority: u32) -> Self {
+        MaterialType::Priority(Box::new(self), priority)
+    }
+
+    pub fn bump(base: MaterialType, height: TextureType, strength: f64) -> Self {
+        MaterialType::Bump(Box::new(base), height, strength)
+    }
+
+    pub fn normal_map(base: MaterialType, normals: TextureType) -> Self {
+        MaterialType::NormalMap(Box::new(base), normals)
+    }
+}
+
+// Steps used to take finite differences of height textures, in uv for image
+// textures and in world units along the tangents for procedural ones
+const BUMP_UV_DELTA: f64 = 1.0 / 1024.0;
+const BUMP_WORLD_DELTA: f64 = 1e-4;
+
+// Outward shading normal tilted by the slope of a height texture. Procedural
+// textures are stepped in space and image textures in uv, each divided by its
+// own step, and the two slopes added since a texture varies in one or the
+// other.
+fn bump_normal(height: &TextureType, strength: f64, rec: &HitRecord) -> Vec3 {
+    let n = rec.outward_normal();
+    let (tangent, bitangent) = (rec.tangent.to_unit(), rec.bitangent.to_unit());
+    let h = height.scalar(rec.u, rec.v, &rec.p);
+    let slope = |du: f64, dv: f64, along: Vec3| {
+        let (u, v) = (rec.u + du * BUMP_UV_DELTA, rec.v + dv * BUMP_UV_DELTA);
+        let in_uv = height.scalar(u, v, &rec.p) - h;
+        let in_space = height.scalar(rec.u, rec.v, &(rec.p + BUMP_WORLD_DELTA * along)) - h;
+        in_uv / BUMP_UV_DELTA + in_space / BUMP_WORLD_DELTA
+    };
+    let dhdu = slope(1.0, 0.0, tangent);
+    let dhdv = slope(0.0, 1.0, bitangent);
+    let perturbed = n - strength * (dhdu * tangent + dhdv * bitangent);
+    perturbed.to_unit()
+}
+
+// Outward shading normal read from a tangent space normal map, stored with
+// each component mapped from [-1, 1] to [0, 1]
+fn mapped_normal(normals: &TextureType, rec: &HitRecord) -> Vec3 {
+    let c = 2.0 * normals.value(rec.u, rec.v, &rec.p) - Vec3::new(1.0, 1.0, 1.0);
+    let n = c.x() * rec.tangent + c.y() * rec.bitangent + c.z() * rec.outward_normal();
+    if n.length_squared() < 1e-12 {
+        rec.outward_normal()
+    } else {
+        n.to_unit()
+    }
 }
 
 pub trait Material {
     fn scatter<T: Rng>(&self, r_in: &Ray, rec: &HitRecord, rng: &mut T) -> Option<(Vec3, Ray)>;
+
+    // BSDF times the cosine of the angle to `wi`, for light arriving from `wi`
+    // and leaving back along `r_in`, with the pdf of scatter picking `wi`.
+    // None for mirror-like materials that only scatter in exact directions,
+    // which light sampling can't reach.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vec3) -> Option<(Vec3, f64)>;
+
+    // Radiance given off by the surface toward the ray origin
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
 }
 
 impl Material for MaterialType {
@@ -24,62 +288,270 @@ impl Material for MaterialType {
             }
             MaterialType::Metal(albedo, fuzz) => {
                 let reflected = Vec3::reflect(Vec3::to_unit(&r_in.direction()), rec.normal);
+                let fuzz = fuzz.scalar(rec.u, rec.v, &rec.p);
                 let scattered =
-                    Ray::new(rec.p, reflected + *fuzz * Vec3::random_in_unit_sphere(rng));
+                    Ray::new(rec.p, reflected + fuzz * Vec3::random_in_unit_sphere(rng));
                 if Vec3::dot(scattered.direction(), rec.normal) > 0.0 {
-                    Some((*albedo, scattered))
+                    Some((albedo.value(rec.u, rec.v, &rec.p), scattered))
                 } else {
                     None
                 }
             }
-            MaterialType::Dielectric(ref_idx) => {
-                let etai_over_etat = if rec.front_face {
+            MaterialType::Dielectric(ior, tint, _) => {
+                let ref_idx = ior.at(r_in.wavelength()) / rec.outside_ior;
+                let tint = tint.value(rec.u, rec.v, &rec.p);
+                Some(scatter_smooth_dielectric(ref_idx, tint, r_in, rec, rng))
+            }
+            MaterialType::Isotropic(albedo) => {
+                let attenuation = albedo.value(rec.u, rec.v, &rec.p);
+                Some((attenuation, Ray::new(rec.p, Vec3::random_unit_vector(rng))))
+            }
+            MaterialType::Conductor(eta, k, roughness) => {
+                let ggx = Ggx::new(roughness.scalar(rec.u, rec.v, &rec.p));
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-r_in.direction().to_unit());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let (wi, m) = ggx.sample_reflection(wo, rng)?;
+                let fresnel = fresnel_conductor(Vec3::dot(wo, m), *eta, *k);
+                let attenuation = fresnel * (ggx.g2(wo, wi) / ggx.g1(wo));
+                Some((attenuation, Ray::new(rec.p, frame.to_world(wi))))
+            }
+            MaterialType::RoughDielectric(ior, roughness, tint, _) => {
+                let ref_idx = ior.at(r_in.wavelength()) / rec.outside_ior;
+                let eta = if rec.front_face {
+                    ref_idx
+                } else {
                     1.0 / ref_idx
+                };
+                let ggx = Ggx::new(roughness.scalar(rec.u, rec.v, &rec.p));
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-r_in.direction().to_unit());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let (wi, transmitted) = ggx.sample_dielectric(wo, eta, rng)?;
+                // The tint colors light each time it is transmitted through the surface
+                let attenuation = if transmitted {
+                    tint.value(rec.u, rec.v, &rec.p)
                 } else {
-                    *ref_idx
+                    Vec3::new(1.0, 1.0, 1.0)
                 };
+                let attenuation = attenuation * (ggx.g2(wo, wi) / ggx.g1(wo));
+                Some((attenuation, Ray::new(rec.p, frame.to_world(wi))))
+            }
+            MaterialType::Principled(params) => params.scatter(r_in, rec, rng),
+            MaterialType::Bump(base, height, strength) => {
+                let normal = bump_normal(height, *strength, rec);
+                base.scatter(r_in, &rec.with_shading_normal(normal), rng)
+            }
+            MaterialType::NormalMap(base, normals) => {
+                let normal = mapped_normal(normals, rec);
+                base.scatter(r_in, &rec.with_shading_normal(normal), rng)
+            }
+            MaterialType::Priority(base, _) | MaterialType::Cutout(base, _, _) => {
+                base.scatter(r_in, rec, rng)
+            }
+            MaterialType::TwoSided(front, back) => {
+                if rec.front_face {
+                    front.scatter(r_in, rec, rng)
+                } else {
+                    back.scatter(r_in, rec, rng)
+                }
+            }
+            MaterialType::ThinDielectric(ior, tint) => {
+                // Light bounces back and forth inside the sheet, summing the
+                // reflections off both faces. Refraction at the two faces
+                // cancels, so transmitted light carries on undeflected.
+                let eta = ior.at(r_in.wavelength()) / rec.outside_ior;
                 let unit_direction = r_in.direction().to_unit();
                 let cos_theta = f64::min(Vec3::dot(-unit_direction, rec.normal), 1.0);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let r = fresnel_dielectric(cos_theta, eta);
+                let reflect_prob = 2.0 * r / (1.0 + r);
+                if rng.gen::<f64>() < reflect_prob {
+                    let reflected = Vec3::reflect(unit_direction, rec.normal);
+                    Some((Vec3::new(1.0, 1.0, 1.0), Ray::new(rec.p, reflected)))
+                } else {
+                    let tint = tint.value(rec.u, rec.v, &rec.p);
+                    Some((tint, Ray::new(rec.p, r_in.direction())))
+                }
+            }
+            MaterialType::Subsurface(ior, _, _) => {
+                // The surface is a smooth interface, the integrator walks the
+                // inside like any other medium
+                let ref_idx = ior.at(r_in.wavelength()) / rec.outside_ior;
+                let white = Vec3::new(1.0, 1.0, 1.0);
+                Some(scatter_smooth_dielectric(ref_idx, white, r_in, rec, rng))
+            }
+            MaterialType::DiffuseLight(_) => None,
+        }
+    }
 
-                let new_direction = if etai_over_etat * sin_theta > 1.0 {
-                    Vec3::reflect(unit_direction, rec.normal)
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vec3) -> Option<(Vec3, f64)> {
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        let wi = wi.to_unit();
+        match self {
+            MaterialType::Lambertian(albedo) => {
+                let cosine = Vec3::dot(wi, rec.normal);
+                if cosine <= 0.0 {
+                    return Some((black, 0.0));
+                }
+                let pdf = cosine / consts::PI;
+                Some((albedo.value(rec.u, rec.v, &rec.p) * pdf, pdf))
+            }
+            MaterialType::Isotropic(albedo) => {
+                let pdf = 1.0 / (4.0 * consts::PI);
+                Some((albedo.value(rec.u, rec.v, &rec.p) * pdf, pdf))
+            }
+            MaterialType::Conductor(eta, k, roughness) => {
+                let ggx = Ggx::new(roughness.scalar(rec.u, rec.v, &rec.p));
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-r_in.direction().to_unit());
+                let wi = frame.to_local(wi);
+                let m = (wo + wi).to_unit();
+                let fresnel = fresnel_conductor(Vec3::dot(wo, m), *eta, *k);
+                Some(ggx.eval_reflection(wo, wi, fresnel).unwrap_or((black, 0.0)))
+            }
+            MaterialType::RoughDielectric(ior, roughness, tint, _) => {
+                let ref_idx = ior.at(r_in.wavelength()) / rec.outside_ior;
+                let eta = if rec.front_face {
+                    ref_idx
+                } else {
+                    1.0 / ref_idx
+                };
+                let ggx = Ggx::new(roughness.scalar(rec.u, rec.v, &rec.p));
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-r_in.direction().to_unit());
+                let wi = frame.to_local(wi);
+                let tint = if wi.z() < 0.0 {
+                    tint.value(rec.u, rec.v, &rec.p)
                 } else {
-                    let reflect_prob = schlick(cos_theta, etai_over_etat);
-                    if rng.gen::<f64>() < reflect_prob {
-                        Vec3::reflect(unit_direction, rec.normal)
-                    } else {
-                        Vec3::refract(unit_direction, rec.normal, etai_over_etat)
-                    }
+                    Vec3::new(1.0, 1.0, 1.0)
                 };
-                Some((Vec3::new(1.0, 1.0, 1.0), Ray::new(rec.p, new_direction)))
+                Some(
+                    ggx.eval_dielectric(wo, wi, eta)
+                        .map_or((black, 0.0), |(f, pdf)| (f * tint, pdf)),
+                )
+            }
+            MaterialType::Principled(params) => Some(params.eval(r_in, rec, wi)),
+            MaterialType::Bump(base, height, strength) => {
+                let normal = bump_normal(height, *strength, rec);
+                base.eval(r_in, &rec.with_shading_normal(normal), wi)
+            }
+            MaterialType::NormalMap(base, normals) => {
+                let normal = mapped_normal(normals, rec);
+                base.eval(r_in, &rec.with_shading_normal(normal), wi)
+            }
+            MaterialType::Priority(base, _) | MaterialType::Cutout(base, _, _) => {
+                base.eval(r_in, rec, wi)
             }
+            MaterialType::TwoSided(front, back) => {
+                if rec.front_face {
+                    front.eval(r_in, rec, wi)
+                } else {
+                    back.eval(r_in, rec, wi)
+                }
+            }
+            MaterialType::Metal(_, _)
+            | MaterialType::Dielectric(_, _, _)
+            | MaterialType::ThinDielectric(_, _)
+            | MaterialType::Subsurface(_, _, _)
+            | MaterialType::DiffuseLight(_) => None,
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        match self {
+            // Lights only shine from their front
+            MaterialType::DiffuseLight(radiance) if rec.front_face => {
+                radiance.value(rec.u, rec.v, &rec.p)
+            }
+            MaterialType::Bump(base, _, _)
+            | MaterialType::NormalMap(base, _)
+            | MaterialType::Priority(base, _)
+            | MaterialType::Cutout(base, _, _) => base.emitted(rec),
+            MaterialType::TwoSided(front, back) => {
+                if rec.front_face {
+                    front.emitted(rec)
+                } else {
+                    // The back faces out of its own side, so lights there shine
+                    let mut facing = rec.clone();
+                    facing.front_face = true;
+                    back.emitted(&facing)
+                }
+            }
+            _ => Vec3::new(0.0, 0.0, 0.0),
         }
     }
 }
 
+// Absorption coefficient that leaves `color` after light travels `distance`
+pub fn absorption_for(color: Vec3, distance: f64) -> Vec3 {
+    let sigma = |c: f64| -c.max(1e-6).ln() / distance;
+    Vec3::new(sigma(color.x()), sigma(color.y()), sigma(color.z()))
+}
+
+// Reflects or refracts off a smooth interface, where `ref_idx` is the index
+// inside relative to outside
+fn scatter_smooth_dielectric<T: Rng>(
+    ref_idx: f64,
+    tint: Vec3,
+    r_in: &Ray,
+    rec: &HitRecord,
+    rng: &mut T,
+) -> (Vec3, Ray) {
+    let etai_over_etat = if rec.front_face {
+        1.0 / ref_idx
+    } else {
+        ref_idx
+    };
+    let unit_direction = r_in.direction().to_unit();
+    let cos_theta = f64::min(Vec3::dot(-unit_direction, rec.normal), 1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let reflected = Vec3::reflect(unit_direction, rec.normal);
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    if etai_over_etat * sin_theta > 1.0 {
+        return (white, Ray::new(rec.p, reflected));
+    }
+    let reflect_prob = schlick(cos_theta, etai_over_etat);
+    if rng.gen::<f64>() < reflect_prob {
+        (white, Ray::new(rec.p, reflected))
+    } else {
+        // The tint colors light each time it is transmitted through the surface
+        let refracted = Vec3::refract(unit_direction, rec.normal, etai_over_etat);
+        (tint, Ray::new(rec.p, refracted))
+    }
+}
+
 fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     let r0 = r0 * r0;
     return r0 + (1.0 - r0) * ((1.0 - cosine).powf(5.0));
 }
 
-pub struct HitRecord {
+#[derive(Clone)]
+pub struct HitRecord<'a> {
     pub t: f64,
     pub p: Vec3,
     pub normal: Vec3,
     pub front_face: bool,
-    pub mat: MaterialType,
+    pub mat: &'a MaterialType,
+    pub tangent: Vec3,        // Surface direction of increasing u
+    pub bitangent: Vec3,      // Surface direction of increasing v
+    pub outside_ior: f64,     // Refractive index on the outward side, set by the integrator
+    pub light: Option<usize>, // Index of the sampled light the surface is part of
     u: f64,
     v: f64,
 }
 
-impl HitRecord {
+impl<'a> HitRecord<'a> {
     pub fn new(
         t: f64,
         p: Vec3,
         outward_normal: Vec3,
-        mat: MaterialType,
+        mat: &'a MaterialType,
         ray: &Ray,
         u: f64,
         v: f64,
@@ -90,14 +562,62 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        // Arbitrary frame, primitives with a uv parameterization override it
+        let (tangent, bitangent) = Vec3::basis(outward_normal);
         HitRecord {
             t,
             p,
             normal,
             front_face,
             mat,
+            tangent,
+            bitangent,
+            outside_ior: 1.0,
+            light: None,
             u,
             v,
         }
     }
+
+    // Sets the tangent frame, made orthonormal to the normal. Degenerate
+    // tangents (e.g. at the poles of a sphere) keep the default frame.
+    pub fn with_tangents(mut self, tangent: Vec3, bitangent: Vec3) -> Self {
+        let n = self.outward_normal();
+        let t = tangent - Vec3::dot(tangent, n) * n;
+        if t.length_squared() < 1e-12 {
+            return self;
+        }
+        let t = t.to_unit();
+        let b = bitangent - Vec3::dot(bitangent, n) * n - Vec3::dot(bitangent, t) * t;
+        self.tangent = t;
+        self.bitangent = if b.length_squared() < 1e-12 {
+            Vec3::cross(n, t)
+        } else {
+            b.to_unit()
+        };
+        self
+    }
+
+    pub fn uv(&self) -> (f64, f64) {
+        (self.u, self.v)
+    }
+
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+
+    // Copy of the record shaded with a different outward normal
+    pub fn with_shading_normal(&self, outward_normal: Vec3) -> Self {
+        let mut rec = self.clone();
+        rec.normal = if self.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+        rec
+    }
 }
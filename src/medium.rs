@@ -0,0 +1,355 @@
+use crate::{
+    aabb::AABB,
+    hittable::{Hittable, HittableType},
+    materials::{HitRecord, MaterialType},
+    ray::Ray,
+    vec::Vec3,
+};
+use rand::Rng;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+// Samples how far light travels through a homogeneous medium before it
+// scatters, up to `max_distance`. The coefficients differ per color channel,
+// so the distance is drawn for a random channel and weighted by the average
+// probability over all three. Returns the distance if the light scattered
+// before `max_distance`, and the weight of the path.
+pub fn free_flight<T: Rng>(
+    sigma_s: Vec3,
+    sigma_a: Vec3,
+    max_distance: f64,
+    rng: &mut T,
+) -> (Option<f64>, Vec3) {
+    let sigma_t = sigma_s + sigma_a;
+    let transmittance = |d: f64| {
+        Vec3::new(
+            (-sigma_t.x() * d).exp(),
+            (-sigma_t.y() * d).exp(),
+            (-sigma_t.z() * d).exp(),
+        )
+    };
+    let average = |v: Vec3| (v.x() + v.y() + v.z()) / 3.0;
+
+    // Purely absorbing media never scatter
+    if average(sigma_s) <= 0.0 {
+        return (None, transmittance(max_distance));
+    }
+
+    let channel = match rng.gen_range(0, 3) {
+        0 => sigma_t.x(),
+        1 => sigma_t.y(),
+        _ => sigma_t.z(),
+    };
+    let distance = -(1.0 - rng.gen::<f64>()).ln() / channel;
+    if distance < max_distance {
+        let tr = transmittance(distance);
+        let pdf = average(sigma_t * tr);
+        if pdf <= 0.0 {
+            return (None, Vec3::new(0.0, 0.0, 0.0));
+        }
+        (Some(distance), sigma_s * tr / pdf)
+    } else {
+        let tr = transmittance(max_distance);
+        let pdf = average(tr);
+        if pdf <= 0.0 {
+            return (None, Vec3::new(0.0, 0.0, 0.0));
+        }
+        (None, tr / pdf)
+    }
+}
+
+// A volume of uniform density filling a closed boundary. Rays passing through
+// it scatter at an exponentially distributed distance, so it should be paired
+// with an isotropic material.
+#[derive(Clone)]
+pub struct ConstantMedium {
+    boundary: Arc<HittableType>,
+    neg_inv_density: f64,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<HittableType>, density: f64) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let (t_enter, t_exit) = match boundary_interval(&self.boundary, mat, ray, t_min, t_max) {
+            None => return None,
+            Some(interval) => interval,
+        };
+
+        let ray_length = ray.direction().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * rand::thread_rng().gen::<f64>().ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // Normal and UV are meaningless inside a volume
+        Some(HitRecord::new(
+            t,
+            ray.at(t),
+            Vec3::new(1.0, 0.0, 0.0),
+            mat,
+            ray,
+            0.0,
+            0.0,
+        ))
+    }
+
+    pub fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+// The part of [t_min, t_max] the ray spends inside a closed boundary, which may
+// start behind the ray origin if the origin is inside.
+pub fn boundary_interval(
+    boundary: &HittableType,
+    mat: &MaterialType,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64)> {
+    let enter = boundary.hit(mat, ray, f64::NEG_INFINITY, f64::INFINITY)?;
+    let exit = boundary.hit(mat, ray, enter.t + 0.0001, f64::INFINITY)?;
+
+    let t_enter = enter.t.max(t_min).max(0.0);
+    let t_exit = exit.t.min(t_max);
+    if t_enter >= t_exit {
+        None
+    } else {
+        Some((t_enter, t_exit))
+    }
+}
+
+// Densities on a regular grid, x varying fastest. On disk the format is a text
+// header line `VOXEL <nx> <ny> <nz>` followed by nx * ny * nz little endian f32s.
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f32>,
+    max_density: f64,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> Self {
+        assert!(nx > 0 && ny > 0 && nz > 0, "Voxel grid has no voxels");
+        assert_eq!(
+            data.len(),
+            nx * ny * nz,
+            "Voxel data does not match grid size"
+        );
+        let max_density = data.iter().fold(0.0_f32, |acc, d| acc.max(*d)) as f64;
+        VoxelGrid {
+            nx,
+            ny,
+            nz,
+            data,
+            max_density,
+        }
+    }
+
+    // Build a grid by sampling `f` at voxel centers in [0, 1]^3
+    pub fn from_fn<F: Fn(Vec3) -> f64>(nx: usize, ny: usize, nz: usize, f: F) -> Self {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let p = Vec3::new(
+                        (x as f64 + 0.5) / nx as f64,
+                        (y as f64 + 0.5) / ny as f64,
+                        (z as f64 + 0.5) / nz as f64,
+                    );
+                    data.push(f(p).max(0.0) as f32);
+                }
+            }
+        }
+        Self::new(nx, ny, nz, data)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        if fields.len() != 4 || fields[0] != "VOXEL" {
+            return Err(invalid("Expected a `VOXEL nx ny nz` header"));
+        }
+        let dims: Vec<usize> = fields[1..]
+            .iter()
+            .map(|f| f.parse().map_err(|_| invalid("Bad voxel grid dimension")))
+            .collect::<io::Result<_>>()?;
+        if dims.contains(&0) {
+            return Err(invalid("Voxel grid has no voxels"));
+        }
+
+        // A corrupt header can't overflow the size, and the data is read as it
+        // arrives rather than allocated up front, so it can't ask for more
+        // memory than the file holds
+        let size = dims[0]
+            .checked_mul(dims[1])
+            .and_then(|n| n.checked_mul(dims[2]))
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| invalid("Voxel grid is too large"))?;
+        let mut bytes = Vec::new();
+        reader.take(size as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Voxel data is shorter than the header says",
+            ));
+        }
+        let data = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        Ok(Self::new(dims[0], dims[1], dims[2], data))
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.ny + y) * self.nx + x] as f64
+    }
+
+    // Trilinearly interpolated density at `p` in [0, 1]^3
+    pub fn density(&self, p: Vec3) -> f64 {
+        let gx = (p.x() * self.nx as f64 - 0.5)
+            .max(0.0)
+            .min((self.nx - 1) as f64);
+        let gy = (p.y() * self.ny as f64 - 0.5)
+            .max(0.0)
+            .min((self.ny - 1) as f64);
+        let gz = (p.z() * self.nz as f64 - 0.5)
+            .max(0.0)
+            .min((self.nz - 1) as f64);
+        let (x0, y0, z0) = (gx as usize, gy as usize, gz as usize);
+        let x1 = (x0 + 1).min(self.nx - 1);
+        let y1 = (y0 + 1).min(self.ny - 1);
+        let z1 = (z0 + 1).min(self.nz - 1);
+        let (fx, fy, fz) = (gx - x0 as f64, gy - y0 as f64, gz - z0 as f64);
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+// A voxel grid stretched over an axis-aligned box, sampled with delta tracking
+// against the grid's maximum density.
+#[derive(Clone)]
+pub struct GridMedium {
+    grid: Arc<VoxelGrid>,
+    bounds: AABB,
+    density_scale: f64,
+    majorant: f64,
+}
+
+impl GridMedium {
+    pub fn new(grid: Arc<VoxelGrid>, min: Vec3, max: Vec3, density_scale: f64) -> Self {
+        let majorant = grid.max_density() * density_scale;
+        GridMedium {
+            grid,
+            bounds: AABB::new(min, max),
+            density_scale,
+            majorant,
+        }
+    }
+
+    fn density_at(&self, p: Vec3) -> f64 {
+        let min = self.bounds.min();
+        let size = self.bounds.max() - min;
+        let local = p - min;
+        let local = Vec3::new(
+            local.x() / size.x(),
+            local.y() / size.y(),
+            local.z() / size.z(),
+        );
+        self.grid.density(local) * self.density_scale
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (t_enter, t_exit) = self.bounds.hit_interval(*ray, t_min, t_max)?;
+
+        let mut rng = rand::thread_rng();
+        let ray_length = ray.direction().length();
+        let mut t = t_enter;
+        loop {
+            // Step with the majorant, then accept with probability density / majorant
+            t -= (1.0 - rng.gen::<f64>()).ln() / (self.majorant * ray_length);
+            if t >= t_exit {
+                return None;
+            }
+            if rng.gen::<f64>() * self.majorant < self.density_at(ray.at(t)) {
+                return Some(HitRecord::new(
+                    t,
+                    ray.at(t),
+                    Vec3::new(1.0, 0.0, 0.0),
+                    mat,
+                    ray,
+                    0.0,
+                    0.0,
+                ));
+            }
+        }
+    }
+
+    // Ratio tracking estimate of the fraction of light passing through the
+    // medium between t_min and t_max
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let (t_enter, t_exit) = match self.bounds.hit_interval(*ray, t_min, t_max) {
+            None => return 1.0,
+            Some(interval) => interval,
+        };
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let mut rng = rand::thread_rng();
+        let ray_length = ray.direction().length();
+        let mut t = t_enter;
+        let mut transmittance = 1.0;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (self.majorant * ray_length);
+            if t >= t_exit {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(ray.at(t)) / self.majorant;
+        }
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        self.bounds
+    }
+}
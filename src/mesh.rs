@@ -0,0 +1,216 @@
+use crate::{
+    aabb::AABB,
+    materials::{HitRecord, MaterialType},
+    ray::Ray,
+    vec::Vec3,
+};
+
+// Most triangles in a leaf of a mesh's BVH
+const LEAF_SIZE: usize = 4;
+// Half-thickness to pad bounding boxes by, flat meshes would have none
+const PAD: f64 = 0.0001;
+
+// A triangle mesh with its own BVH over the triangles. Put one in an Arc and
+// instance it to place copies that share the triangles, each costing only a
+// transform.
+#[derive(Clone)]
+pub struct Mesh {
+    positions: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,       // Per vertex, empty to use barycentrics
+    triangles: Vec<[usize; 3]>, // Counter-clockwise seen from outside
+    root: MeshNode,
+}
+
+#[derive(Clone)]
+enum MeshNode {
+    Branch(Box<MeshNode>, Box<MeshNode>, AABB),
+    Leaf(usize, usize, AABB), // Range of triangles
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>) -> Self {
+        assert!(!triangles.is_empty(), "Mesh needs at least one triangle");
+        assert!(
+            triangles.iter().flatten().all(|&i| i < positions.len()),
+            "Mesh triangle refers to a missing vertex"
+        );
+        let mut triangles = triangles;
+        let root = MeshNode::new(&positions, &mut triangles, 0);
+        Mesh {
+            positions,
+            uvs: Vec::new(),
+            triangles,
+            root,
+        }
+    }
+
+    // Texture coordinates for each vertex, in the order of the positions
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Self {
+        assert_eq!(
+            uvs.len(),
+            self.positions.len(),
+            "Mesh needs a uv per vertex"
+        );
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let mut closest_so_far = t_max;
+        let mut found = None;
+        // AABB::hit checks each slab on its own, which culls almost nothing
+        // over a mesh's tightly packed triangles
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match node {
+                MeshNode::Branch(left, right, bb) => {
+                    if bb.hit_interval(*ray, t_min, closest_so_far).is_some() {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+                MeshNode::Leaf(start, end, bb) => {
+                    if bb.hit_interval(*ray, t_min, closest_so_far).is_none() {
+                        continue;
+                    }
+                    for index in *start..*end {
+                        if let Some((t, b1, b2)) = self.intersect(index, ray, t_min, closest_so_far)
+                        {
+                            closest_so_far = t;
+                            found = Some((index, t, b1, b2));
+                        }
+                    }
+                }
+            }
+        }
+
+        let (index, t, b1, b2) = found?;
+        let [i0, i1, i2] = self.triangles[index];
+        let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let outward_normal = Vec3::cross(e1, e2).to_unit();
+
+        // Without uvs each triangle is mapped on its own, by barycentrics
+        let (uv0, uv1, uv2) = if self.uvs.is_empty() {
+            ((0.0, 0.0), (1.0, 0.0), (0.0, 1.0))
+        } else {
+            (self.uvs[i0], self.uvs[i1], self.uvs[i2])
+        };
+        let b0 = 1.0 - b1 - b2;
+        let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+
+        // Directions the uvs increase in across the triangle
+        let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+        let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+        let det = du1 * dv2 - dv1 * du2;
+        let (tangent, bitangent) = if det.abs() < 1e-12 {
+            Vec3::basis(outward_normal)
+        } else {
+            ((dv2 * e1 - dv1 * e2) / det, (du1 * e2 - du2 * e1) / det)
+        };
+
+        Some(
+            HitRecord::new(t, ray.at(t), outward_normal, mat, ray, u, v)
+                .with_tangents(tangent, bitangent),
+        )
+    }
+
+    // Ray parameter and barycentrics of the ray crossing a triangle
+    // (Möller-Trumbore)
+    fn intersect(
+        &self,
+        index: usize,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, f64, f64)> {
+        let [i0, i1, i2] = self.triangles[index];
+        let p0 = self.positions[i0];
+        let (e1, e2) = (self.positions[i1] - p0, self.positions[i2] - p0);
+        let pvec = Vec3::cross(ray.direction(), e2);
+        let det = Vec3::dot(e1, pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin() - p0;
+        let b1 = Vec3::dot(tvec, pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = Vec3::cross(tvec, e1);
+        let b2 = Vec3::dot(ray.direction(), qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = Vec3::dot(e2, qvec) * inv_det;
+        if t > t_min && t < t_max {
+            Some((t, b1, b2))
+        } else {
+            None
+        }
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        self.root.bounds()
+    }
+}
+
+impl MeshNode {
+    // Splits the triangles in half along the axis their centroids are most
+    // spread out on. `offset` is where `triangles` starts in the whole mesh.
+    fn new(positions: &[Vec3], triangles: &mut [[usize; 3]], offset: usize) -> Self {
+        let centroid = |t: &[usize; 3]| (positions[t[0]] + positions[t[1]] + positions[t[2]]) / 3.0;
+
+        if triangles.len() <= LEAF_SIZE {
+            let corners = triangles.iter().flatten().map(|&i| positions[i]);
+            return MeshNode::Leaf(offset, offset + triangles.len(), bounds(corners));
+        }
+
+        let extent = {
+            let b = bounds(triangles.iter().map(centroid));
+            b.max() - b.min()
+        };
+        let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            0
+        } else if extent.y() >= extent.z() {
+            1
+        } else {
+            2
+        };
+        triangles
+            .sort_unstable_by(|a, b| centroid(a).comp(axis).total_cmp(&centroid(b).comp(axis)));
+
+        let mid = triangles.len() / 2;
+        let (left, right) = triangles.split_at_mut(mid);
+        let left = MeshNode::new(positions, left, offset);
+        let right = MeshNode::new(positions, right, offset + mid);
+        let bb = AABB::surrounding_box(left.bounds(), right.bounds());
+        MeshNode::Branch(Box::new(left), Box::new(right), bb)
+    }
+
+    fn bounds(&self) -> AABB {
+        match self {
+            MeshNode::Branch(_, _, bb) | MeshNode::Leaf(_, _, bb) => *bb,
+        }
+    }
+}
+
+// Padded box around some points
+fn bounds<I: Iterator<Item = Vec3>>(points: I) -> AABB {
+    let pad = Vec3::new(PAD, PAD, PAD);
+    let mut lo = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut hi = -lo;
+    for p in points {
+        lo = Vec3::new(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z()));
+        hi = Vec3::new(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z()));
+    }
+    AABB::new(lo - pad, hi + pad)
+}
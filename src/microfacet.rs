@@ -0,0 +1,231 @@
+use crate::rand::Rng;
+use crate::vec::Vec3;
+use std::f64::consts;
+
+// GGX (Trowbridge-Reitz) microfacet distribution. Directions are given in a
+// local frame where the macro surface normal is +z.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // Roughness is perceptual, squared to get the distribution width. Very
+    // small widths are clamped so a roughness of 0 still samples cleanly.
+    pub fn new(roughness: f64) -> Self {
+        let r = roughness.max(0.0).min(1.0);
+        Ggx {
+            alpha: (r * r).max(1e-4),
+        }
+    }
+
+    // Density of microfacet normals
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = m.z() * m.z() * (a2 - 1.0) + 1.0;
+        a2 / (consts::PI * t * t)
+    }
+
+    // Smith shadowing auxiliary function
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    // Fraction of microfacets visible from `w`
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated fraction visible from both directions
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Samples a microfacet normal from the distribution of normals visible
+    // from `wo` (Heitz 2018). `wo` must be above the surface.
+    pub fn sample_visible(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view so the distribution becomes a hemisphere
+        let vh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).to_unit();
+        let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / len2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(vh, t1);
+
+        // Uniform point on the projected disk, squashed onto the visible half
+        let r = u1.sqrt();
+        let phi = 2.0 * consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // Unstretch back to the ellipsoid
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(0.0)).to_unit()
+    }
+
+    // Reflection off facets with Fresnel reflectance `fresnel`, as the BRDF
+    // times the cosine of `wi`. Returns it with the pdf of sample_reflection
+    // picking `wi`.
+    pub fn eval_reflection(&self, wo: Vec3, wi: Vec3, fresnel: Vec3) -> Option<(Vec3, f64)> {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        let m = (wo + wi).to_unit();
+        let d = self.d(m);
+        let f = fresnel * (d * self.g2(wo, wi) / (4.0 * wo.z()));
+        let pdf = self.g1(wo) * d / (4.0 * wo.z());
+        Some((f, pdf))
+    }
+
+    // Both halves of a rough dielectric interface, matching sample_dielectric.
+    // Transmission ignores the eta squared radiance scaling, like sampling.
+    pub fn eval_dielectric(&self, wo: Vec3, wi: Vec3, eta: f64) -> Option<(f64, f64)> {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return None;
+        }
+        if wi.z() > 0.0 {
+            let m = (wo + wi).to_unit();
+            let fresnel = fresnel_dielectric(Vec3::dot(wo, m), eta);
+            let (f, pdf) = self.eval_reflection(wo, wi, Vec3::new(fresnel, fresnel, fresnel))?;
+            return Some((f.x(), fresnel * pdf));
+        }
+
+        // Generalized half vector of the refraction
+        let m = -(wo + eta * wi);
+        if m.length_squared() <= 0.0 {
+            return None;
+        }
+        let m = if m.z() < 0.0 {
+            -m.to_unit()
+        } else {
+            m.to_unit()
+        };
+        let (cos_o, cos_i) = (Vec3::dot(wo, m), Vec3::dot(wi, m));
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return None;
+        }
+        let denom = cos_o + eta * cos_i;
+        let jacobian = eta * eta * cos_i.abs() / (denom * denom);
+        let transmit = 1.0 - fresnel_dielectric(cos_o, eta);
+        let d = self.d(m);
+        let f = transmit * d * self.g2(wo, wi) * cos_o * jacobian / wo.z();
+        let pdf = transmit * self.g1(wo) * d * cos_o * jacobian / wo.z();
+        Some((f, pdf))
+    }
+
+    // Mirrors `wo` about a visible microfacet, giving the reflected direction
+    // and the facet normal. Rays reflected below the surface would bounce
+    // again between facets and are dropped. The path weight is
+    // F * g2 / g1.
+    pub fn sample_reflection<T: Rng>(&self, wo: Vec3, rng: &mut T) -> Option<(Vec3, Vec3)> {
+        let m = self.sample_visible(wo, rng.gen(), rng.gen());
+        let wi = Vec3::reflect(-wo, m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        Some((wi, m))
+    }
+
+    // Reflects or refracts `wo` through a visible microfacet of a dielectric
+    // interface, picked by its Fresnel reflectance. Returns the new direction
+    // and whether it was transmitted. The path weight is g2 / g1.
+    pub fn sample_dielectric<T: Rng>(
+        &self,
+        wo: Vec3,
+        eta: f64,
+        rng: &mut T,
+    ) -> Option<(Vec3, bool)> {
+        let m = self.sample_visible(wo, rng.gen(), rng.gen());
+        let cos_o = Vec3::dot(wo, m);
+        if rng.gen::<f64>() < fresnel_dielectric(cos_o, eta) {
+            let wi = Vec3::reflect(-wo, m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            Some((wi, false))
+        } else {
+            let cos_t = (1.0 - (1.0 - cos_o * cos_o) / (eta * eta)).sqrt();
+            let wi = -wo / eta + (cos_o / eta - cos_t) * m;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            Some((wi, true))
+        }
+    }
+}
+
+// Orthonormal frame around a normal for moving between world and local space
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    pub fn new(n: Vec3) -> Self {
+        let (s, t) = Vec3::basis(n);
+        Frame { s, t, n }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(v, self.s),
+            Vec3::dot(v, self.t),
+            Vec3::dot(v, self.n),
+        )
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.s + v.y() * self.t + v.z() * self.n
+    }
+}
+
+// Unpolarized reflectance of a dielectric interface, where `eta` is the ratio
+// of the refractive index on the far side over the near side
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.max(0.0).min(1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+// Unpolarized reflectance of a conductor with complex refractive index
+// eta + ik, per color channel
+pub fn fresnel_conductor(cos_i: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    let cos_i = cos_i.max(0.0).min(1.0);
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Vec3::new(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
@@ -0,0 +1,88 @@
+use crate::materials::{HitRecord, MaterialType};
+use crate::vec::Vec3;
+use std::ptr;
+
+// The dielectric media a path is currently inside. Where media overlap the
+// one with the highest priority fills the space, so a liquid can be modeled
+// slightly larger than the cavity of its glass and ice can float in water.
+#[derive(Clone)]
+pub struct MediumStack<'a> {
+    media: Vec<&'a MaterialType>,
+}
+
+impl<'a> MediumStack<'a> {
+    pub fn new() -> Self {
+        MediumStack { media: Vec::new() }
+    }
+
+    // The medium the path travels through, ties go to the one entered last
+    pub fn current(&self) -> Option<&'a MaterialType> {
+        let mut current: Option<&'a MaterialType> = None;
+        for mat in &self.media {
+            if current.map_or(true, |c| mat.priority() >= c.priority()) {
+                current = Some(mat);
+            }
+        }
+        current
+    }
+
+    // Scattering and absorption coefficients of the current medium. Paths
+    // enter media through the front of their surfaces, so two-sided ones use
+    // their front.
+    pub fn interior(&self) -> Option<(Vec3, Vec3)> {
+        self.current().and_then(|mat| mat.interior(true))
+    }
+
+    fn ior_without(&self, skip: Option<&MaterialType>, wavelength: Option<f64>) -> f64 {
+        let mut rest = self.clone();
+        if let Some(mat) = skip {
+            rest.remove(mat);
+        }
+        rest.current()
+            .and_then(|mat| mat.ior(wavelength, true))
+            .unwrap_or(1.0)
+    }
+
+    fn remove(&mut self, mat: &MaterialType) {
+        if let Some(i) = self.media.iter().rposition(|m| ptr::eq(*m, mat)) {
+            self.media.remove(i);
+        }
+    }
+
+    // Refractive index on the outer side of the surface that was hit, or None
+    // if the hit is inside a higher priority medium and should be passed
+    // through as if the surface wasn't there
+    pub fn outside_ior(&self, rec: &HitRecord<'a>, wavelength: Option<f64>) -> Option<f64> {
+        // Surfaces that don't bound a medium sit in the current one
+        if rec.mat.ior(wavelength, rec.front_face).is_none() {
+            return Some(self.ior_without(None, wavelength));
+        }
+        if rec.front_face {
+            match self.current() {
+                Some(top) if top.priority() > rec.mat.priority() => None,
+                _ => Some(self.ior_without(None, wavelength)),
+            }
+        } else {
+            match self.current() {
+                Some(top) if !ptr::eq(top, rec.mat) && self.contains(rec.mat) => None,
+                _ => Some(self.ior_without(Some(rec.mat), wavelength)),
+            }
+        }
+    }
+
+    fn contains(&self, mat: &MaterialType) -> bool {
+        self.media.iter().any(|m| ptr::eq(*m, mat))
+    }
+
+    // Records the path passing through the surface of `mat`
+    pub fn cross(&mut self, mat: &'a MaterialType, entering: bool) {
+        if mat.ior(None, entering).is_none() {
+            return;
+        }
+        if entering {
+            self.media.push(mat);
+        } else {
+            self.remove(mat);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::materials::{HitRecord, Material};
+use crate::medium::free_flight;
+use crate::nested::MediumStack;
+use crate::rand::Rng;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::world::World;
+
+// Caustic photon mapping (Jensen 1996). Before rendering, photons are shot
+// from the lights and followed through glass and off mirrors. Where they land
+// on a diffuse surface they're stored, and the path tracer estimates caustics
+// from how densely they gathered instead of hoping a bounced ray finds a
+// light through the glass.
+//
+// Photons carry no wavelength, so caustics from dispersive glass don't split
+// into colors.
+
+// Most bounces a photon takes before it's dropped
+const MAX_BOUNCES: usize = 50;
+// Photons looked up for each estimate, and the farthest to look for them
+const GATHER_COUNT: usize = 50;
+const GATHER_RADIUS: f64 = 0.1;
+// Shots, as multiples of the photons wanted, after which a map that has
+// stored almost nothing is given up on, and after which shooting stops anyway
+const GIVE_UP_SHOTS: usize = 4;
+const MAX_SHOTS: usize = 100;
+
+#[derive(Clone, Copy)]
+struct Photon {
+    p: Vec3,
+    direction: Vec3, // Travelling toward the surface
+    power: Vec3,
+    axis: u8, // Splitting plane of the kd-tree node it's at
+}
+
+// Photons in a balanced kd-tree, each node in the middle of the slice it
+// splits
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+}
+
+impl PhotonMap {
+    // Shoots photons until `count` of them have reached a diffuse surface
+    // through glass or off mirrors. Lights at infinity aren't shot from.
+    pub fn caustics<T: Rng>(world: &World, count: usize, rng: &mut T) -> Self {
+        let mut photons = Vec::with_capacity(count);
+        let mut emitted = 0;
+        while photons.len() < count && emitted < MAX_SHOTS * count {
+            // Scenes with little or no glass would never fill the map
+            if emitted == GIVE_UP_SHOTS * count && photons.len() < count / 100 {
+                break;
+            }
+            let (light, probability) = match world.sample_light_by_power(rng) {
+                Some(picked) => picked,
+                None => break,
+            };
+            emitted += 1;
+            let emission = match light.sample_emission(rng) {
+                Some(emission) => emission,
+                None => continue,
+            };
+            let cosine = emission
+                .normal
+                .map_or(1.0, |n| Vec3::dot(n, emission.direction).abs());
+            let power = emission.radiance * cosine
+                / (probability * emission.pdf_position * emission.pdf_direction);
+            let ray = Ray::new(emission.origin, emission.direction);
+            photons.extend(trace(world, ray, power, rng));
+        }
+
+        // Every photon shot shares the light's power, stored or not
+        for photon in photons.iter_mut() {
+            photon.power = photon.power / emitted as f64;
+        }
+        build(&mut photons);
+        PhotonMap { photons }
+    }
+
+    // Caustic light leaving the hit back along `ray`, from the photons
+    // nearest it. Mirror-like surfaces never store photons and get none.
+    pub fn radiance(&self, ray: &Ray, rec: &HitRecord) -> Vec3 {
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        let mut nearest = Vec::with_capacity(GATHER_COUNT);
+        let mut radius2 = GATHER_RADIUS * GATHER_RADIUS;
+        search(&self.photons, rec.p, &mut nearest, &mut radius2);
+        if nearest.is_empty() {
+            return black;
+        }
+
+        // Cone filter, so photons at the edge of the disc count for less and
+        // caustics keep sharp edges
+        let radius = radius2.sqrt();
+        let mut total = black;
+        for (d2, photon) in nearest {
+            let wi = -photon.direction;
+            let cosine = Vec3::dot(wi, rec.normal);
+            // Photons that landed on the other side of the surface
+            if cosine <= 0.0 {
+                continue;
+            }
+            if let Some((f, _)) = rec.mat.eval(ray, rec, wi) {
+                let weight = 1.0 - d2.sqrt() / radius;
+                total = total + weight / cosine * f * photon.power;
+            }
+        }
+        3.0 * total / (std::f64::consts::PI * radius2)
+    }
+}
+
+// Follows a photon through mirror-like bounces, returning where it lands on a
+// diffuse surface if it bounced at least once on the way. Light reaching a
+// diffuse surface directly is left to the path tracer.
+fn trace<T: Rng>(world: &World, mut ray: Ray, mut power: Vec3, rng: &mut T) -> Option<Photon> {
+    let mut media = MediumStack::new();
+    let mut specular = false;
+    for _ in 0..MAX_BOUNCES {
+        let mut rec = world.hit(&ray, 0.001, f64::INFINITY)?;
+
+        // Light absorbed inside glass dims the photon, and light scattered
+        // there is no longer part of a caustic
+        if let Some((sigma_s, sigma_a)) = media.interior() {
+            let length = ray.direction().length();
+            let (scatter_at, weight) = free_flight(sigma_s, sigma_a, rec.t * length, rng);
+            if scatter_at.is_some() {
+                return None;
+            }
+            power = power * weight;
+        }
+        match media.outside_ior(&rec, ray.wavelength()) {
+            Some(ior) => rec.outside_ior = ior,
+            None => {
+                media.cross(rec.mat, rec.front_face);
+                ray = Ray::new(rec.p, ray.direction());
+                continue;
+            }
+        }
+
+        let (attenuation, scattered) = rec.mat.scatter(&ray, &rec, rng)?;
+        if rec.mat.eval(&ray, &rec, scattered.direction()).is_some() {
+            return if specular {
+                Some(Photon {
+                    p: rec.p,
+                    direction: ray.direction().to_unit(),
+                    power,
+                    axis: 0,
+                })
+            } else {
+                None
+            };
+        }
+
+        if (Vec3::dot(scattered.direction(), rec.outward_normal()) > 0.0) != rec.front_face {
+            media.cross(rec.mat, rec.front_face);
+        }
+        power = power * attenuation;
+        if power == Vec3::new(0.0, 0.0, 0.0) {
+            return None;
+        }
+        specular = true;
+        ray = scattered;
+    }
+    None
+}
+
+// Arranges the photons into a kd-tree in place, splitting each slice at its
+// median along the axis the photons are most spread out on
+fn build(photons: &mut [Photon]) {
+    if photons.len() <= 1 {
+        return;
+    }
+
+    let mut lo = photons[0].p;
+    let mut hi = lo;
+    for photon in photons.iter() {
+        let p = photon.p;
+        lo = Vec3::new(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z()));
+        hi = Vec3::new(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z()));
+    }
+    let extent = hi - lo;
+    let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+        0
+    } else if extent.y() >= extent.z() {
+        1
+    } else {
+        2
+    };
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.p.comp(axis).total_cmp(&b.p.comp(axis)));
+    photons[mid].axis = axis;
+    let (left, right) = photons.split_at_mut(mid);
+    build(left);
+    build(&mut right[1..]);
+}
+
+// Collects up to GATHER_COUNT photons within `radius2` of `p`, by squared
+// distance. Once there are that many the search closes in to the farthest.
+fn search<'a>(
+    photons: &'a [Photon],
+    p: Vec3,
+    nearest: &mut Vec<(f64, &'a Photon)>,
+    radius2: &mut f64,
+) {
+    if photons.is_empty() {
+        return;
+    }
+    let mid = photons.len() / 2;
+    let photon = &photons[mid];
+    let delta = p.comp(photon.axis) - photon.p.comp(photon.axis);
+    let (near, far) = if delta < 0.0 {
+        (&photons[..mid], &photons[mid + 1..])
+    } else {
+        (&photons[mid + 1..], &photons[..mid])
+    };
+
+    search(near, p, nearest, radius2);
+
+    let d2 = (photon.p - p).length_squared();
+    if d2 < *radius2 {
+        if nearest.len() < GATHER_COUNT {
+            nearest.push((d2, photon));
+        } else {
+            let farthest = (0..nearest.len())
+                .max_by(|&i, &j| nearest[i].0.total_cmp(&nearest[j].0))
+                .unwrap();
+            nearest[farthest] = (d2, photon);
+        }
+        if nearest.len() == GATHER_COUNT {
+            *radius2 = nearest.iter().map(|(d2, _)| *d2).fold(0.0, f64::max);
+        }
+    }
+
+    if delta * delta < *radius2 {
+        search(far, p, nearest, radius2);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::materials::HitRecord;
+use crate::microfacet::{fresnel_dielectric, Frame, Ggx};
+use crate::rand::Rng;
+use crate::ray::Ray;
+use crate::texture::{Texture, TextureType};
+use crate::vec::Vec3;
+use std::f64::consts;
+
+// Clearcoat is a thin, glossy varnish layer of fixed roughness
+const CLEARCOAT_IOR: f64 = 1.5;
+const CLEARCOAT_ROUGHNESS: f64 = 0.1;
+
+// Layered material in the style of the Disney principled BSDF. Scalar
+// parameters read the scalar value of their texture and sit in [0, 1].
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: TextureType,
+    pub metallic: TextureType,
+    pub roughness: TextureType,
+    pub specular: TextureType, // Dielectric reflectance, 0.5 is an F0 of 4%
+    pub clearcoat: TextureType,
+    pub sheen: TextureType, // Extra grazing reflection for cloth
+    pub transmission: TextureType,
+    pub ior: f64, // Used for transmission
+}
+
+fn constant(value: f64) -> TextureType {
+    TextureType::Constant(Vec3::new(value, value, value))
+}
+
+fn schlick(f0: Vec3, cosine: f64) -> Vec3 {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    f0 + (1.0 - cosine).max(0.0).powi(5) * (white - f0)
+}
+
+impl Principled {
+    // A rough white plastic, fields can be overridden with struct update syntax
+    pub fn new(base_color: TextureType) -> Self {
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            ior: 1.5,
+        }
+    }
+
+    // glTF metallic-roughness factors, plus the KHR_materials_transmission,
+    // KHR_materials_ior and KHR_materials_clearcoat extension factors. glTF
+    // has no sheen amount, so it is left off.
+    pub fn from_gltf(
+        base_color: Vec3,
+        metallic: f64,
+        roughness: f64,
+        transmission: f64,
+        ior: f64,
+        clearcoat: f64,
+    ) -> Self {
+        Principled {
+            metallic: constant(metallic),
+            roughness: constant(roughness),
+            clearcoat: constant(clearcoat),
+            transmission: constant(transmission),
+            ior,
+            ..Self::new(TextureType::Constant(base_color))
+        }
+    }
+
+    // Wavefront MTL diffuse color (Kd), specular color (Ks), specular exponent
+    // (Ns), optical density (Ni) and dissolve (d). The Phong exponent is
+    // converted to the GGX width it roughly matches, alpha^2 = 2 / (Ns + 2).
+    pub fn from_mtl(kd: Vec3, ks: Vec3, ns: f64, ni: f64, d: f64) -> Self {
+        let alpha = (2.0 / (ns.max(0.0) + 2.0)).sqrt();
+        let specular = (ks.x() + ks.y() + ks.z()) / 3.0;
+        Principled {
+            roughness: constant(alpha.sqrt()),
+            specular: constant(specular.max(0.0).min(1.0)),
+            transmission: constant((1.0 - d).max(0.0).min(1.0)),
+            ior: if ni > 0.0 { ni } else { 1.5 },
+            ..Self::new(TextureType::Constant(kd))
+        }
+    }
+
+    // Texture values at the hit and how much each lobe contributes, from
+    // the top: clearcoat, then metal, glass or specular over diffuse. Lobe
+    // weights only depend on the outgoing direction, so scattering can pick
+    // one lobe with probability equal to its weight.
+    fn lobes(&self, rec: &HitRecord, cos_o: f64) -> (Params, [f64; LOBES]) {
+        let (u, v) = rec.uv();
+        let p = &rec.p;
+        let params = Params {
+            base_color: self.base_color.value(u, v, p),
+            roughness: self.roughness.scalar(u, v, p),
+            sheen: self.sheen.scalar(u, v, p),
+            eta: if rec.front_face {
+                self.ior
+            } else {
+                1.0 / self.ior
+            },
+        };
+        let transmission = self.transmission.scalar(u, v, p);
+
+        // Leaving a transmissive object, only the glass lobe applies
+        if !rec.front_face && transmission > 0.0 {
+            return (params, [0.0, 0.0, 1.0, 0.0, 0.0]);
+        }
+
+        let metallic = self.metallic.scalar(u, v, p);
+        let coat = self.clearcoat.scalar(u, v, p) * fresnel_dielectric(cos_o, CLEARCOAT_IOR);
+        let rest = 1.0 - coat;
+        let base = rest * (1.0 - metallic) * (1.0 - transmission);
+        let f0 = 0.08 * self.specular.scalar(u, v, p);
+        let specular = f0 + (1.0 - f0) * (1.0 - cos_o).max(0.0).powi(5);
+        let weights = [
+            coat,
+            rest * metallic,
+            rest * (1.0 - metallic) * transmission,
+            base * specular,
+            base * (1.0 - specular),
+        ];
+        (params, weights)
+    }
+
+    pub fn scatter<T: Rng>(&self, r_in: &Ray, rec: &HitRecord, rng: &mut T) -> Option<(Vec3, Ray)> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-r_in.direction().to_unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let (params, weights) = self.lobes(rec, wo.z());
+        let white = Vec3::new(1.0, 1.0, 1.0);
+
+        // Each lobe's sample carries only that lobe's reflectance
+        let mut pick = rng.gen::<f64>() * weights.iter().sum::<f64>();
+        let lobe = (0..LOBES)
+            .find(|&i| {
+                pick -= weights[i];
+                pick < 0.0
+            })
+            .unwrap_or(DIFFUSE);
+
+        let ggx = Ggx::new(params.roughness);
+        let (attenuation, wi) = match lobe {
+            COAT => {
+                let coat = Ggx::new(CLEARCOAT_ROUGHNESS);
+                let (wi, _) = coat.sample_reflection(wo, rng)?;
+                (white * (coat.g2(wo, wi) / coat.g1(wo)), wi)
+            }
+            METAL => {
+                let (wi, m) = ggx.sample_reflection(wo, rng)?;
+                let fresnel = schlick(params.base_color, Vec3::dot(wo, m));
+                (fresnel * (ggx.g2(wo, wi) / ggx.g1(wo)), wi)
+            }
+            GLASS => {
+                let (wi, transmitted) = ggx.sample_dielectric(wo, params.eta, rng)?;
+                let tint = if transmitted {
+                    params.base_color
+                } else {
+                    white
+                };
+                (tint * (ggx.g2(wo, wi) / ggx.g1(wo)), wi)
+            }
+            SPECULAR => {
+                let (wi, _) = ggx.sample_reflection(wo, rng)?;
+                (white * (ggx.g2(wo, wi) / ggx.g1(wo)), wi)
+            }
+            _ => {
+                let wi = (Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector(rng)).to_unit();
+                (params.diffuse(wo, wi) * consts::PI, wi)
+            }
+        };
+        Some((attenuation, Ray::new(rec.p, frame.to_world(wi))))
+    }
+
+    // Sum of the lobes, each weighted by how likely scattering is to pick it
+    pub fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vec3) -> (Vec3, f64) {
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-r_in.direction().to_unit());
+        let wi = frame.to_local(wi.to_unit());
+        if wo.z() <= 0.0 {
+            return (black, 0.0);
+        }
+        let (params, weights) = self.lobes(rec, wo.z());
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let ggx = Ggx::new(params.roughness);
+
+        let mut total = (black, 0.0);
+        for (lobe, weight) in weights.iter().enumerate() {
+            if *weight <= 0.0 {
+                continue;
+            }
+            let eval = match lobe {
+                COAT => Ggx::new(CLEARCOAT_ROUGHNESS).eval_reflection(wo, wi, white),
+                METAL => {
+                    let m = (wo + wi).to_unit();
+                    let fresnel = schlick(params.base_color, Vec3::dot(wo, m));
+                    ggx.eval_reflection(wo, wi, fresnel)
+                }
+                GLASS => ggx.eval_dielectric(wo, wi, params.eta).map(|(f, pdf)| {
+                    let tint = if wi.z() < 0.0 {
+                        params.base_color
+                    } else {
+                        white
+                    };
+                    (f * tint, pdf)
+                }),
+                SPECULAR => ggx.eval_reflection(wo, wi, white),
+                _ if wi.z() > 0.0 => {
+                    let pdf = wi.z() / consts::PI;
+                    Some((params.diffuse(wo, wi) * wi.z(), pdf))
+                }
+                _ => None,
+            };
+            if let Some((f, pdf)) = eval {
+                total = (total.0 + *weight * f, total.1 + weight * pdf);
+            }
+        }
+        total
+    }
+}
+
+const LOBES: usize = 5;
+const COAT: usize = 0;
+const METAL: usize = 1;
+const GLASS: usize = 2;
+const SPECULAR: usize = 3;
+const DIFFUSE: usize = 4;
+
+// Texture values looked up at a hit
+struct Params {
+    base_color: Vec3,
+    roughness: f64,
+    sheen: f64,
+    eta: f64, // Refractive index across the surface, relative to this side
+}
+
+impl Params {
+    // Lambertian BRDF, with sheen brightening grazing angles
+    fn diffuse(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        let half = (wo + wi).to_unit();
+        let sheen = self.sheen * (1.0 - Vec3::dot(wi, half)).max(0.0).powi(5);
+        (self.base_color + sheen * Vec3::new(1.0, 1.0, 1.0)) / consts::PI
+    }
+}
@@ -4,6 +4,7 @@ use crate::vec::Vec3;
 pub struct Ray {
     origin: Vec3,
     vec: Vec3,
+    wavelength: Option<f64>, // In nanometers, only set when rendering spectrally
 }
 
 impl Ray {
@@ -11,9 +12,15 @@ impl Ray {
         Ray {
             origin: origin,
             vec: dir,
+            wavelength: None,
         }
     }
 
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Ray {
+        self.wavelength = wavelength;
+        self
+    }
+
     pub fn origin(&self) -> Vec3 {
         self.origin
     }
@@ -22,6 +29,10 @@ impl Ray {
         self.vec
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + self.vec * t
     }
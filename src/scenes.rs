@@ -1,22 +1,104 @@
 use crate::{
-    hittable::{HittableList, HittableType},
+    background::Background,
+    environment::EnvironmentMap,
+    hittable::HittableType,
+    image::{Filter, Image, Wrap},
+    lights::LightType,
     materials::MaterialType,
+    medium::VoxelGrid,
+    mesh::Mesh,
+    principled::Principled,
+    sky::Sky,
     solids::Sphere,
+    spectrum::Ior,
     texture::TextureType,
+    transform::Transform,
     vec::Vec3,
+    world::World,
 };
 use rand::{thread_rng, Rng};
+use std::sync::Arc;
 
 pub enum Scene {
     THREE_BALLS,
     RANDOM_BALLS(u16),
     SHINY,
+    INSTANCES,
+    MESHES,
+    BOXES,
+    SHAPES,
+    CSG,
+    SMOKE,
+    SMOKE_RING,
+    CLOUD,
+    TEXTURED,
+    NOISE,
+    LAYERED,
+    SCRATCHED,
+    BUMPY,
+    MICROFACET,
+    PRINCIPLED,
+    DISPERSION,
+    ABSORPTION,
+    NESTED,
+    SUBSURFACE,
+    CUTOUT,
+    THIN,
+    LIGHTS,
+    OUTDOORS,
+    STUDIO,
+    FIREFLIES(u16),
+    CAUSTICS,
 }
 
-pub fn use_scene(s: Scene) -> HittableList {
+// Cornell box walls, open at the top and front so the sky lights the room
+fn cornell_walls(world: &mut World) {
+    let red = MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.65, 0.05, 0.05)));
+    let white = MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.73, 0.73, 0.73)));
+    let green = MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.12, 0.45, 0.15)));
+
+    world.add(HittableType::yz_rect(0.0, 555.0, 0.0, 555.0, 555.0), green);
+    world.add(HittableType::yz_rect(0.0, 555.0, 0.0, 555.0, 0.0), red);
+    world.add(
+        HittableType::xz_rect(0.0, 555.0, 0.0, 555.0, 0.0),
+        white.clone(),
+    );
+    world.add(HittableType::xy_rect(0.0, 555.0, 0.0, 555.0, 555.0), white);
+}
+
+// Spins a profile of (radius, height) points from the bottom up around the y
+// axis, with u running around and v up the profile
+fn lathe(profile: &[(f64, f64)], segments: usize) -> Mesh {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    for (j, (r, y)) in profile.iter().enumerate() {
+        // The seam is doubled so its uvs can wrap
+        for k in 0..=segments {
+            let theta = 2.0 * std::f64::consts::PI * k as f64 / segments as f64;
+            positions.push(Vec3::new(r * theta.cos(), *y, r * theta.sin()));
+            uvs.push((
+                k as f64 / segments as f64,
+                j as f64 / (profile.len() - 1) as f64,
+            ));
+        }
+    }
+
+    let mut triangles = Vec::new();
+    let row = segments + 1;
+    for j in 0..profile.len() - 1 {
+        for k in 0..segments {
+            let (a, b) = (j * row + k, (j + 1) * row + k);
+            triangles.push([a, b, a + 1]);
+            triangles.push([b, b + 1, a + 1]);
+        }
+    }
+    Mesh::new(positions, triangles).with_uvs(uvs)
+}
+
+pub fn use_scene(s: Scene) -> World {
     match s {
         Scene::THREE_BALLS => {
-            let mut world = HittableList::new();
+            let mut world = World::new();
             let tex =
                 TextureType::Checker(0.01, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.12, 0.45, 0.15));
 
@@ -26,49 +108,50 @@ pub fn use_scene(s: Scene) -> HittableList {
             );
             // ground
             world.add(
-                HittableType::sphere(Vec3::new(0.0, -100.5, -1.0), 100.0),
-                MaterialType::Metal(Vec3::new(0.8, 0.8, 0.9), 0.1),
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::metal(Vec3::new(0.8, 0.8, 0.9), 0.1),
             );
 
             world.add(
                 HittableType::sphere(Vec3::new(1.0, 0.0, -1.0), 0.5),
-                MaterialType::Metal(Vec3::new(0.40625, 0.1015625, 0.52734375), 0.0),
+                MaterialType::metal(Vec3::new(0.40625, 0.1015625, 0.52734375), 0.0),
             );
             // bubble
             world.add(
-                HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5),
-                MaterialType::Dielectric(1.5),
-            );
-            world.add(
-                HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), -0.45),
-                MaterialType::Dielectric(1.5),
+                HittableType::difference(
+                    Arc::new(HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5)),
+                    Arc::new(HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.45)),
+                ),
+                MaterialType::dielectric(1.5),
             );
 
             return world;
         }
 
         Scene::SHINY => {
-            let mut world = HittableList::new();
+            let mut world = World::new();
             let tex =
                 TextureType::Checker(0.01, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.12, 0.45, 0.15));
-            let metal = MaterialType::Metal(Vec3::new(0.9, 0.9, 0.8), 0.0);
+            let metal = MaterialType::metal(Vec3::new(0.9, 0.9, 0.8), 0.0);
 
-            world.add(HittableType::sphere(Vec3::new(0.0, 0.0, -1.0), 0.5), metal);
+            world.add(
+                HittableType::sphere(Vec3::new(0.0, 0.0, -1.0), 0.5),
+                metal.clone(),
+            );
             // ground
             world.add(
-                HittableType::sphere(Vec3::new(0.0, -100.5, -1.0), 100.0),
-                metal,
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                metal.clone(),
             );
 
             world.add(HittableType::sphere(Vec3::new(1.0, 0.0, -1.0), 0.5), metal);
             // bubble
             world.add(
-                HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5),
-                MaterialType::Dielectric(1.5),
-            );
-            world.add(
-                HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), -0.45),
-                MaterialType::Dielectric(1.5),
+                HittableType::difference(
+                    Arc::new(HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5)),
+                    Arc::new(HittableType::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.45)),
+                ),
+                MaterialType::dielectric(1.5),
             );
 
             return world;
@@ -76,7 +159,7 @@ pub fn use_scene(s: Scene) -> HittableList {
 
         Scene::RANDOM_BALLS(num) => {
             let mut rng = thread_rng();
-            let mut world = HittableList::new();
+            let mut world = World::new();
 
             for _ in 0..num {
                 world.add(
@@ -88,7 +171,7 @@ pub fn use_scene(s: Scene) -> HittableList {
                         ),
                         rng.gen_range(1.0, 20.0),
                     ),
-                    MaterialType::Metal(
+                    MaterialType::metal(
                         Vec3::new(
                             rng.gen_range(0.0, 1.0),
                             rng.gen_range(0.0, 1.0),
@@ -101,5 +184,920 @@ pub fn use_scene(s: Scene) -> HittableList {
 
             return world;
         }
+
+        Scene::INSTANCES => {
+            let mut world = World::new();
+            // Every ellipsoid below shares this one sphere
+            let unit_sphere = Arc::new(HittableType::sphere(Vec3::new(0.0, 0.0, 0.0), 1.0));
+
+            // ground
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::metal(Vec3::new(0.8, 0.8, 0.9), 0.1),
+            );
+
+            for i in 0..5 {
+                let transform = Transform::scale(Vec3::new(0.4, 0.15, 0.15))
+                    .then(Transform::rotate_z(36.0 * i as f64))
+                    .then(Transform::translate(Vec3::new(
+                        -1.0 + 0.5 * i as f64,
+                        0.0,
+                        -1.0,
+                    )));
+                world.add(
+                    HittableType::instance(unit_sphere.clone(), transform),
+                    MaterialType::metal(Vec3::new(0.9, 0.6, 0.2 * i as f64), 0.05),
+                );
+            }
+
+            return world;
+        }
+
+        Scene::MESHES => {
+            let mut rng = thread_rng();
+            let mut world = World::new();
+            // ground
+            world.add(
+                HittableType::sphere(Vec3::new(0.0, -100.5, -1.0), 100.0),
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.5, 0.5, 0.5))),
+            );
+
+            // A thousand chess pawns, all sharing the triangles of one
+            let pawn = lathe(
+                &[
+                    (0.0, 0.0),
+                    (0.5, 0.0),
+                    (0.5, 0.1),
+                    (0.35, 0.2),
+                    (0.2, 0.5),
+                    (0.35, 0.6),
+                    (0.15, 0.65),
+                    (0.25, 0.75),
+                    (0.3, 0.9),
+                    (0.2, 1.05),
+                    (0.0, 1.1),
+                ],
+                32,
+            );
+            let pawn = Arc::new(HittableType::Mesh(pawn));
+            for i in 0..1000 {
+                let (x, z) = ((i % 40) as f64, (i / 40) as f64);
+                let transform = Transform::scale(Vec3::new(0.15, 0.15, 0.15))
+                    .then(Transform::rotate_y(rng.gen_range(0.0, 360.0)))
+                    .then(Transform::translate(Vec3::new(
+                        -3.0 + 0.15 * x,
+                        -0.5,
+                        -0.8 - 0.3 * z,
+                    )));
+                let mat = if (i + i / 40) % 2 == 0 {
+                    MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.9, 0.85, 0.7)))
+                } else {
+                    MaterialType::metal(Vec3::new(0.2, 0.2, 0.25), 0.2)
+                };
+                world.add(HittableType::instance(pawn.clone(), transform), mat);
+            }
+
+            return world;
+        }
+
+        Scene::BOXES => {
+            let mut world = World::new();
+            let white =
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.73, 0.73, 0.73)));
+            cornell_walls(&mut world);
+
+            let tall =
+                Transform::rotate_y(15.0).then(Transform::translate(Vec3::new(265.0, 0.0, 295.0)));
+            world.add(
+                HittableType::instance(
+                    Arc::new(HittableType::cuboid(
+                        Vec3::new(0.0, 0.0, 0.0),
+                        Vec3::new(165.0, 330.0, 165.0),
+                    )),
+                    tall,
+                ),
+                white,
+            );
+
+            let short =
+                Transform::rotate_y(-18.0).then(Transform::translate(Vec3::new(130.0, 0.0, 65.0)));
+            world.add(
+                HittableType::instance(
+                    Arc::new(HittableType::cuboid(
+                        Vec3::new(0.0, 0.0, 0.0),
+                        Vec3::new(165.0, 165.0, 165.0),
+                    )),
+                    short,
+                ),
+                MaterialType::metal(Vec3::new(0.8, 0.85, 0.88), 0.0),
+            );
+
+            return world;
+        }
+
+        Scene::SHAPES => {
+            let mut world = World::new();
+            let tex = TextureType::Checker(0.1, Vec3::new(0.1, 0.1, 0.1), Vec3::new(0.9, 0.9, 0.9));
+            let gold = MaterialType::metal(Vec3::new(0.9, 0.7, 0.3), 0.05);
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(tex),
+            );
+            world.add(
+                HittableType::cylinder(Vec3::new(-1.0, -0.5, -1.0), 0.25, 0.7, true),
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.7, 0.2, 0.2))),
+            );
+            world.add(
+                HittableType::cone(Vec3::new(0.0, -0.5, -1.0), 0.3, 0.8, true),
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.2, 0.3, 0.7))),
+            );
+            world.add(
+                HittableType::torus(Vec3::new(1.0, -0.3, -1.0), 0.3, 0.1),
+                gold,
+            );
+            world.add(
+                HittableType::disk(Vec3::new(0.0, 0.2, -2.0), Vec3::new(0.0, 0.3, 1.0), 0.5),
+                MaterialType::metal(Vec3::new(0.9, 0.9, 0.9), 0.0),
+            );
+
+            return world;
+        }
+
+        Scene::CSG => {
+            let mut world = World::new();
+            let tex = TextureType::Checker(0.1, Vec3::new(0.1, 0.1, 0.1), Vec3::new(0.9, 0.9, 0.9));
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(tex),
+            );
+
+            // biconvex lens from two overlapping spheres
+            world.add(
+                HittableType::intersection(
+                    Arc::new(HittableType::sphere(Vec3::new(-1.0, 0.0, -0.2), 1.0)),
+                    Arc::new(HittableType::sphere(Vec3::new(-1.0, 0.0, -1.8), 1.0)),
+                ),
+                MaterialType::dielectric(1.5),
+            );
+
+            // box with a spherical bite taken out of it
+            world.add(
+                HittableType::difference(
+                    Arc::new(HittableType::cuboid(
+                        Vec3::new(-0.3, -0.5, -1.3),
+                        Vec3::new(0.3, 0.1, -0.7),
+                    )),
+                    Arc::new(HittableType::sphere(Vec3::new(0.0, 0.1, -0.9), 0.3)),
+                ),
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.7, 0.3, 0.2))),
+            );
+
+            // hollow glass bubble
+            world.add(
+                HittableType::difference(
+                    Arc::new(HittableType::sphere(Vec3::new(1.0, 0.0, -1.0), 0.5)),
+                    Arc::new(HittableType::sphere(Vec3::new(1.0, 0.0, -1.0), 0.45)),
+                ),
+                MaterialType::dielectric(1.5),
+            );
+
+            return world;
+        }
+
+        Scene::SMOKE => {
+            let mut world = World::new();
+            cornell_walls(&mut world);
+
+            let tall = Arc::new(HittableType::instance(
+                Arc::new(HittableType::cuboid(
+                    Vec3::new(0.0, 0.0, 0.0),
+                    Vec3::new(165.0, 330.0, 165.0),
+                )),
+                Transform::rotate_y(15.0).then(Transform::translate(Vec3::new(265.0, 0.0, 295.0))),
+            ));
+            world.add(
+                HittableType::constant_medium(tall, 0.01),
+                MaterialType::Isotropic(TextureType::Constant(Vec3::new(0.0, 0.0, 0.0))),
+            );
+
+            let short = Arc::new(HittableType::instance(
+                Arc::new(HittableType::cuboid(
+                    Vec3::new(0.0, 0.0, 0.0),
+                    Vec3::new(165.0, 165.0, 165.0),
+                )),
+                Transform::rotate_y(-18.0).then(Transform::translate(Vec3::new(130.0, 0.0, 65.0))),
+            ));
+            world.add(
+                HittableType::constant_medium(short, 0.01),
+                MaterialType::Isotropic(TextureType::Constant(Vec3::new(1.0, 1.0, 1.0))),
+            );
+
+            return world;
+        }
+
+        Scene::SMOKE_RING => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.4, 0.5, 0.3))),
+            );
+
+            // A 24^3 grid of densities read from disk
+            let grid =
+                VoxelGrid::load("volumes/smoke_ring.vox").expect("Could not load voxel grid");
+            world.add(
+                HittableType::grid_medium(
+                    Arc::new(grid),
+                    Vec3::new(-1.0, -0.5, -2.0),
+                    Vec3::new(1.0, 1.5, 0.0),
+                    30.0,
+                ),
+                MaterialType::Isotropic(TextureType::Constant(Vec3::new(0.9, 0.9, 0.9))),
+            );
+            world.add_light(LightType::directional(
+                Vec3::new(0.3, -1.0, -0.4),
+                Vec3::new(1.5, 1.4, 1.2),
+                1.0,
+            ));
+
+            return world;
+        }
+
+        Scene::CLOUD => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Constant(Vec3::new(0.4, 0.5, 0.3))),
+            );
+
+            // A lumpy cloud made of a few overlapping gaussian puffs
+            let puffs = [
+                (Vec3::new(0.5, 0.45, 0.5), 0.2),
+                (Vec3::new(0.3, 0.4, 0.45), 0.14),
+                (Vec3::new(0.7, 0.4, 0.55), 0.15),
+                (Vec3::new(0.55, 0.6, 0.4), 0.12),
+            ];
+            let grid = VoxelGrid::from_fn(48, 48, 48, |p| {
+                puffs
+                    .iter()
+                    .map(|(center, size)| {
+                        let d2 = (p - *center).length_squared();
+                        (-d2 / (size * size)).exp()
+                    })
+                    .sum::<f64>()
+            });
+            world.add(
+                HittableType::grid_medium(
+                    Arc::new(grid),
+                    Vec3::new(-1.2, -0.6, -2.2),
+                    Vec3::new(1.2, 1.2, 0.2),
+                    40.0,
+                ),
+                MaterialType::Isotropic(TextureType::Constant(Vec3::new(0.95, 0.95, 0.95))),
+            );
+
+            return world;
+        }
+
+        Scene::TEXTURED => {
+            let mut world = World::new();
+            let image =
+                Arc::new(Image::load("images/lens_closeup.ppm").expect("Could not load texture"));
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Image(
+                    image.clone(),
+                    Filter::Bilinear,
+                    Wrap::Mirror,
+                )),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::image(image.clone())),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::Image(image, Filter::Nearest, Wrap::Clamp)),
+            );
+
+            return world;
+        }
+
+        Scene::NOISE => {
+            let mut world = World::new();
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::noise(1, 4.0)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-1.1, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::turbulence(2, 4.0)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.0, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::marble(3, 2.0)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(1.1, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::wood(4, 12.0)),
+            );
+
+            return world;
+        }
+
+        Scene::LAYERED => {
+            let mut world = World::new();
+
+            // Rotated tiles, darkened by noise so they look worn
+            let tiles = TextureType::rotate(
+                TextureType::Checker(0.05, Vec3::new(0.2, 0.2, 0.2), Vec3::new(0.8, 0.8, 0.75)),
+                30.0,
+            );
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::multiply(
+                    tiles,
+                    TextureType::ramp(
+                        TextureType::noise(5, 6.0),
+                        vec![
+                            (0.3, Vec3::new(0.5, 0.45, 0.4)),
+                            (0.6, Vec3::new(1.0, 1.0, 1.0)),
+                        ],
+                    ),
+                )),
+            );
+
+            // Wood and marble blended through a turbulent mask
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::mix(
+                    TextureType::wood(6, 12.0),
+                    TextureType::marble(7, 2.0),
+                    TextureType::ramp(
+                        TextureType::turbulence(8, 3.0),
+                        vec![
+                            (0.25, Vec3::new(0.0, 0.0, 0.0)),
+                            (0.35, Vec3::new(1.0, 1.0, 1.0)),
+                        ],
+                    ),
+                )),
+            );
+
+            // Color ramp over a stretched, shifted checker
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.0), 0.5),
+                MaterialType::Lambertian(TextureType::ramp(
+                    TextureType::scale_offset(
+                        TextureType::Checker(
+                            0.02,
+                            Vec3::new(0.0, 0.0, 0.0),
+                            Vec3::new(1.0, 1.0, 1.0),
+                        ),
+                        (1.0, 4.0),
+                        (0.25, 0.0),
+                    ),
+                    vec![
+                        (0.0, Vec3::new(0.1, 0.1, 0.6)),
+                        (1.0, Vec3::new(0.9, 0.3, 0.1)),
+                    ],
+                )),
+            );
+
+            return world;
+        }
+
+        Scene::SCRATCHED => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.1,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            // Polished steel with rough, tarnished streaks
+            let streaks = TextureType::ramp(
+                TextureType::turbulence(9, 3.0),
+                vec![
+                    (0.2, Vec3::new(0.0, 0.0, 0.0)),
+                    (0.5, Vec3::new(1.0, 1.0, 1.0)),
+                ],
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.0), 0.5),
+                MaterialType::Metal(
+                    TextureType::mix(
+                        TextureType::Constant(Vec3::new(0.9, 0.9, 0.9)),
+                        TextureType::Constant(Vec3::new(0.5, 0.4, 0.3)),
+                        streaks.clone(),
+                    ),
+                    TextureType::ramp(
+                        streaks,
+                        vec![
+                            (0.0, Vec3::new(0.0, 0.0, 0.0)),
+                            (1.0, Vec3::new(0.4, 0.4, 0.4)),
+                        ],
+                    ),
+                ),
+            );
+
+            // Green bottle glass
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.0), 0.5),
+                MaterialType::Dielectric(
+                    Ior::Constant(1.5),
+                    TextureType::Constant(Vec3::new(0.7, 0.95, 0.75)),
+                    Vec3::new(0.0, 0.0, 0.0),
+                ),
+            );
+
+            return world;
+        }
+
+        Scene::BUMPY => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.1,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            // Hammered copper
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.0), 0.5),
+                MaterialType::bump(
+                    MaterialType::metal(Vec3::new(0.9, 0.6, 0.45), 0.05),
+                    TextureType::noise(3, 12.0),
+                    0.02,
+                ),
+            );
+
+            // Stone with a rough, pitted surface
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.0), 0.5),
+                MaterialType::bump(
+                    MaterialType::lambertian(Vec3::new(0.6, 0.55, 0.5)),
+                    TextureType::turbulence(5, 6.0),
+                    0.05,
+                ),
+            );
+
+            return world;
+        }
+
+        Scene::MICROFACET => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.1,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            let materials = vec![
+                MaterialType::gold(0.1),
+                MaterialType::copper(0.35),
+                MaterialType::aluminium(0.6),
+                MaterialType::rough_dielectric(1.5, 0.3),
+            ];
+            for (i, mat) in materials.into_iter().enumerate() {
+                let x = -0.9 + 0.6 * i as f64;
+                world.add(HittableType::sphere(Vec3::new(x, -0.2, -1.2), 0.28), mat);
+            }
+
+            return world;
+        }
+
+        Scene::PRINCIPLED => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.1,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            let materials = vec![
+                // Car paint
+                Principled {
+                    roughness: TextureType::Constant(Vec3::new(0.4, 0.4, 0.4)),
+                    clearcoat: TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+                    ..Principled::new(TextureType::Constant(Vec3::new(0.6, 0.05, 0.05)))
+                },
+                // Brushed brass from glTF factors
+                Principled::from_gltf(Vec3::new(0.9, 0.7, 0.3), 1.0, 0.45, 0.0, 1.5, 0.0),
+                // Velvet
+                Principled {
+                    roughness: TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+                    specular: TextureType::Constant(Vec3::new(0.0, 0.0, 0.0)),
+                    sheen: TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+                    ..Principled::new(TextureType::Constant(Vec3::new(0.25, 0.05, 0.4)))
+                },
+                // Frosted glass from an MTL definition
+                Principled::from_mtl(
+                    Vec3::new(0.9, 0.95, 1.0),
+                    Vec3::new(0.5, 0.5, 0.5),
+                    200.0,
+                    1.5,
+                    0.0,
+                ),
+            ];
+            for (i, mat) in materials.into_iter().enumerate() {
+                let x = -0.9 + 0.6 * i as f64;
+                world.add(
+                    HittableType::sphere(Vec3::new(x, -0.2, -1.2), 0.28),
+                    MaterialType::principled(mat),
+                );
+            }
+
+            return world;
+        }
+
+        Scene::DISPERSION => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.02,
+                    Vec3::new(0.05, 0.05, 0.05),
+                    Vec3::new(0.95, 0.95, 0.95),
+                )),
+            );
+
+            // Dense flint glass and diamond, best rendered with SPECTRAL on
+            world.add(
+                HittableType::sphere(Vec3::new(-0.55, 0.0, -1.0), 0.5),
+                MaterialType::dielectric(Ior::flint()),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.55, 0.0, -1.0), 0.5),
+                MaterialType::dielectric(Ior::diamond()),
+            );
+
+            return world;
+        }
+
+        Scene::ABSORPTION => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.1,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            // The same glass gets deeper in color as it gets thicker
+            let glass = MaterialType::colored_glass(1.5, Vec3::new(0.2, 0.5, 0.9), 0.5);
+            for (x, radius) in &[(-0.85, 0.12), (-0.45, 0.22), (0.05, 0.3), (0.7, 0.4)] {
+                world.add(
+                    HittableType::sphere(Vec3::new(*x, radius - 0.5, -1.3), *radius),
+                    glass.clone(),
+                );
+            }
+
+            return world;
+        }
+
+        Scene::NESTED => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.05,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            // A tumbler of water with an ice cube. The water overlaps the
+            // glass walls so there is no gap, the glass wins where they meet.
+            let base = Vec3::new(0.0, -0.5, -0.8);
+            world.add(
+                HittableType::difference(
+                    Arc::new(HittableType::cylinder(base, 0.3, 0.7, true)),
+                    Arc::new(HittableType::cylinder(
+                        base + Vec3::new(0.0, 0.05, 0.0),
+                        0.27,
+                        0.7,
+                        true,
+                    )),
+                ),
+                MaterialType::dielectric(Ior::bk7()).with_priority(2),
+            );
+            world.add(
+                HittableType::cylinder(base + Vec3::new(0.0, 0.04, 0.0), 0.28, 0.45, true),
+                MaterialType::colored_glass(Ior::water(), Vec3::new(0.6, 0.85, 0.95), 0.5)
+                    .with_priority(1),
+            );
+            world.add(
+                HittableType::instance(
+                    Arc::new(HittableType::cuboid(
+                        Vec3::new(-0.08, -0.08, -0.08),
+                        Vec3::new(0.08, 0.08, 0.08),
+                    )),
+                    Transform::rotate_y(30.0)
+                        .then(Transform::rotate_x(20.0))
+                        .then(Transform::translate(base + Vec3::new(0.05, 0.44, 0.0))),
+                ),
+                MaterialType::dielectric(1.31).with_priority(3),
+            );
+
+            return world;
+        }
+
+        Scene::SUBSURFACE => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.5, 0.5, 0.5)),
+            );
+
+            // Wax, jade and skin, next to plain diffuse for comparison
+            world.add(
+                HittableType::sphere(Vec3::new(-0.9, -0.2, -1.2), 0.28),
+                MaterialType::lambertian(Vec3::new(0.9, 0.85, 0.7)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.3, -0.2, -1.2), 0.28),
+                MaterialType::subsurface(1.45, Vec3::new(0.9, 0.85, 0.7), 0.05),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.3, -0.2, -1.2), 0.28),
+                MaterialType::subsurface(1.6, Vec3::new(0.3, 0.8, 0.45), 0.08),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.9, -0.2, -1.2), 0.28),
+                MaterialType::subsurface(1.4, Vec3::new(0.9, 0.6, 0.5), 0.02),
+            );
+
+            return world;
+        }
+
+        Scene::CUTOUT => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.4, 0.5, 0.3)),
+            );
+
+            // A fence panel with square holes
+            world.add(
+                HittableType::xy_rect(-1.2, 1.2, -0.5, 0.2, -1.6),
+                MaterialType::cutout(
+                    MaterialType::lambertian(Vec3::new(0.6, 0.4, 0.25)),
+                    TextureType::Checker(0.03, Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)),
+                ),
+            );
+
+            // A lacy shell, the far side shows through the holes
+            world.add(
+                HittableType::sphere(Vec3::new(0.0, -0.1, -1.0), 0.4),
+                MaterialType::cutout(
+                    MaterialType::metal(Vec3::new(0.9, 0.8, 0.5), 0.1),
+                    TextureType::ramp(
+                        TextureType::noise(11, 8.0),
+                        vec![
+                            (0.45, Vec3::new(0.0, 0.0, 0.0)),
+                            (0.55, Vec3::new(1.0, 1.0, 1.0)),
+                        ],
+                    ),
+                ),
+            );
+
+            return world;
+        }
+
+        Scene::THIN => {
+            let mut world = World::new();
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::Lambertian(TextureType::Checker(
+                    0.1,
+                    Vec3::new(0.1, 0.1, 0.1),
+                    Vec3::new(0.9, 0.9, 0.9),
+                )),
+            );
+
+            // Soap bubble, a single thin film rather than a glass shell
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, -0.05, -1.0), 0.45),
+                MaterialType::thin_dielectric(1.33),
+            );
+
+            // A tinted window pane, which doesn't shift what is behind it
+            world.add(
+                HittableType::instance(
+                    Arc::new(HittableType::xy_rect(-0.3, 0.3, -0.5, 0.2, 0.0)),
+                    Transform::rotate_y(-30.0)
+                        .then(Transform::translate(Vec3::new(0.1, 0.0, -0.9))),
+                ),
+                MaterialType::ThinDielectric(
+                    Ior::Constant(1.5),
+                    TextureType::Constant(Vec3::new(0.7, 0.85, 1.0)),
+                ),
+            );
+
+            // Cards that are red on the front and gold on the back, one
+            // turned around
+            let card = Arc::new(HittableType::xy_rect(-0.2, 0.2, -0.5, 0.1, 0.0));
+            for (degrees, x) in &[(40.0, 0.55), (140.0, 1.0)] {
+                world.add(
+                    HittableType::instance(
+                        card.clone(),
+                        Transform::rotate_y(*degrees)
+                            .then(Transform::translate(Vec3::new(*x, 0.0, -1.3))),
+                    ),
+                    MaterialType::two_sided(
+                        MaterialType::lambertian(Vec3::new(0.7, 0.1, 0.1)),
+                        MaterialType::gold(0.2),
+                    ),
+                );
+            }
+
+            return world;
+        }
+
+        Scene::LIGHTS => {
+            let mut world = World::new();
+            world.set_background(Background::Solid(Vec3::new(0.01, 0.01, 0.02)));
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.6, 0.6, 0.6)),
+            );
+            world.add(
+                HittableType::xy_rect(-3.0, 3.0, -0.5, 2.0, -2.5),
+                MaterialType::lambertian(Vec3::new(0.6, 0.6, 0.6)),
+            );
+
+            world.add(
+                HittableType::sphere(Vec3::new(-0.7, -0.2, -1.2), 0.3),
+                MaterialType::lambertian(Vec3::new(0.8, 0.3, 0.2)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.0, -0.2, -1.2), 0.3),
+                MaterialType::copper(0.3),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.7, -0.2, -1.2), 0.3),
+                MaterialType::principled(Principled {
+                    clearcoat: TextureType::Constant(Vec3::new(1.0, 1.0, 1.0)),
+                    ..Principled::new(TextureType::Constant(Vec3::new(0.1, 0.3, 0.7)))
+                }),
+            );
+
+            // A warm bulb, a cool spotlight picking out the left ball, a low
+            // sun with soft shadows and a glowing orb
+            world.add_light(LightType::point(
+                Vec3::new(0.5, 0.6, -0.6),
+                Vec3::new(0.8, 0.6, 0.4),
+            ));
+            world.add_light(LightType::spot(
+                Vec3::new(-0.7, 1.2, -0.8),
+                Vec3::new(0.0, -1.0, -0.3),
+                Vec3::new(1.5, 1.5, 2.0),
+                10.0,
+                20.0,
+            ));
+            world.add_light(LightType::directional(
+                Vec3::new(1.0, -0.6, -0.5),
+                Vec3::new(0.4, 0.35, 0.3),
+                2.0,
+            ));
+            world.add(
+                HittableType::sphere(Vec3::new(-0.2, -0.4, -0.7), 0.1),
+                MaterialType::diffuse_light(Vec3::new(4.0, 6.0, 4.0)),
+            );
+
+            return world;
+        }
+
+        Scene::OUTDOORS => {
+            let mut world = World::new();
+            // Late afternoon sun on a clear day
+            let sky = Sky::new(Vec3::new(-1.0, 0.35, -0.6), 3.0);
+            world.add_light(sky.sun_light());
+            world.set_background(Background::Sky(sky));
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.45, 0.4, 0.35)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.2), 0.5),
+                MaterialType::lambertian(Vec3::new(0.8, 0.8, 0.8)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.2), 0.5),
+                MaterialType::aluminium(0.15),
+            );
+
+            return world;
+        }
+
+        Scene::STUDIO => {
+            let mut world = World::new();
+            let studio =
+                Arc::new(Image::load("images/studio.hdr").expect("Could not load environment"));
+            world.set_background(Background::Environment(EnvironmentMap::new(studio, 0.4)));
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.6, 0.6, 0.6)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.2), 0.5),
+                MaterialType::principled(Principled::new(TextureType::Constant(Vec3::new(
+                    0.7, 0.1, 0.1,
+                )))),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.2), 0.5),
+                MaterialType::gold(0.3),
+            );
+
+            return world;
+        }
+
+        Scene::FIREFLIES(num) => {
+            let mut rng = thread_rng();
+            let mut world = World::new();
+            world.set_background(Background::Solid(Vec3::new(0.0, 0.0, 0.0)));
+            world.use_light_bvh();
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.5, 0.5, 0.5)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.2), 0.5),
+                MaterialType::lambertian(Vec3::new(0.8, 0.8, 0.8)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.2), 0.5),
+                MaterialType::copper(0.2),
+            );
+
+            // Small glowing balls scattered through the scene
+            for _ in 0..num {
+                let color = Vec3::new(
+                    rng.gen_range(0.5, 1.0),
+                    rng.gen_range(0.3, 1.0),
+                    rng.gen_range(0.0, 0.4),
+                );
+                world.add(
+                    HittableType::sphere(
+                        Vec3::new(
+                            rng.gen_range(-3.0, 3.0),
+                            rng.gen_range(-0.4, 1.5),
+                            rng.gen_range(-5.0, 0.5),
+                        ),
+                        rng.gen_range(0.01, 0.03),
+                    ),
+                    MaterialType::diffuse_light(rng.gen_range(10.0, 40.0) * color),
+                );
+            }
+
+            return world;
+        }
+
+        Scene::CAUSTICS => {
+            let mut world = World::new();
+            world.set_background(Background::Solid(Vec3::new(0.0, 0.0, 0.0)));
+
+            world.add(
+                HittableType::plane(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                MaterialType::lambertian(Vec3::new(0.7, 0.7, 0.7)),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(-0.6, 0.0, -1.2), 0.5),
+                MaterialType::dielectric(1.5),
+            );
+            world.add(
+                HittableType::sphere(Vec3::new(0.6, 0.0, -1.2), 0.5),
+                MaterialType::colored_glass(Ior::bk7(), Vec3::new(0.9, 0.5, 0.2), 0.5),
+            );
+            // A small lamp up and behind, so the spheres focus it toward the camera
+            world.add(
+                HittableType::sphere(Vec3::new(0.0, 2.0, -2.5), 0.15),
+                MaterialType::diffuse_light(Vec3::new(60.0, 60.0, 60.0)),
+            );
+
+            return world;
+        }
     }
 }
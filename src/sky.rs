@@ -0,0 +1,125 @@
+use crate::lights::LightType;
+use crate::spectrum::xyz_to_rgb;
+use crate::vec::Vec3;
+use std::f64::consts;
+
+// Scales the sky's luminance, in thousands of candela per square meter, down
+// to the brightness range the rest of the renderer works in
+const SKY_SCALE: f64 = 0.05;
+
+// Irradiance of the sun before the atmosphere dims and reddens it
+const SUN_IRRADIANCE: f64 = 3.0;
+
+// The sun is about half a degree across
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+// Analytic daylight sky from Preetham, Shirley and Smits (1999). Turbidity is
+// the haziness of the air, from 2 for a very clear day to 10 for a hazy one.
+#[derive(Clone)]
+pub struct Sky {
+    sun: Vec3, // Unit vector toward the sun
+    turbidity: f64,
+    perez: [[f64; 5]; 3], // Distribution coefficients for Y, x and y
+    zenith: Vec3,         // Y, x and y straight up
+}
+
+impl Sky {
+    pub fn new(sun_direction: Vec3, turbidity: f64) -> Self {
+        let sun = sun_direction.to_unit();
+        let t = turbidity;
+        let theta_s = sun.y().max(0.0).min(1.0).acos();
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (consts::PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s1, s2, s3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s1)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s1 + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s1 + 0.25886);
+        let zenith_yy = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s1)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s1 + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s1 + 0.26688);
+
+        Sky {
+            sun,
+            turbidity,
+            perez,
+            zenith: Vec3::new(zenith_y, zenith_x, zenith_yy),
+        }
+    }
+
+    fn perez(coeffs: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = *coeffs;
+        (1.0 + a * (b / cos_theta).exp())
+            * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+
+    // Sky color looking along `direction`. Below the horizon the sky is held
+    // at its horizon color.
+    pub fn color(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.to_unit();
+        let cos_theta = direction.y().max(0.01);
+        let gamma = Vec3::dot(direction, self.sun).max(-1.0).min(1.0).acos();
+        let theta_s = self.sun.y().max(0.0).min(1.0).acos();
+
+        let channel = |i: usize, zenith: f64| {
+            zenith * Self::perez(&self.perez[i], cos_theta, gamma)
+                / Self::perez(&self.perez[i], 1.0, theta_s)
+        };
+        let big_y = channel(0, self.zenith.x()) * SKY_SCALE;
+        let x = channel(1, self.zenith.y());
+        let y = channel(2, self.zenith.z());
+
+        let xyz = Vec3::new(x * big_y / y, big_y, (1.0 - x - y) * big_y / y);
+        let rgb = xyz_to_rgb(xyz);
+        Vec3::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+    }
+
+    // Sunlight matching the sky, dimmed and reddened by Rayleigh scattering and
+    // haze along its path through the air
+    pub fn sun_light(&self) -> LightType {
+        // Once it sets the ground hides it
+        if self.sun.y() <= 0.0 {
+            let black = Vec3::new(0.0, 0.0, 0.0);
+            return LightType::directional(-self.sun, black, SUN_ANGULAR_DIAMETER);
+        }
+        let theta_s = self.sun.y().min(1.0).acos();
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        // Red, green and blue wavelengths in micrometers
+        let transmittance = |lambda: f64| {
+            let rayleigh = -0.008735 * lambda.powf(-4.08) * air_mass;
+            let aerosol = -beta * lambda.powf(-1.3) * air_mass;
+            (rayleigh + aerosol).exp()
+        };
+        let color = Vec3::new(
+            transmittance(0.68),
+            transmittance(0.55),
+            transmittance(0.44),
+        );
+        LightType::directional(-self.sun, SUN_IRRADIANCE * color, SUN_ANGULAR_DIAMETER)
+    }
+}
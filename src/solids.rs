@@ -1,4 +1,5 @@
 use crate::{
+    aabb::AABB,
     materials::{HitRecord, MaterialType},
     ray::Ray,
     vec::Vec3,
@@ -27,7 +28,13 @@ impl Sphere {
         self.radius
     }
 
-    pub fn hit(&self, mat: MaterialType, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
         let oc = ray.origin() - self.center();
         let a = ray.direction().length_squared();
         let half_b = Vec3::dot(oc, ray.direction());
@@ -47,15 +54,12 @@ impl Sphere {
                 let angle = (Vec3::dot(outward_normal, Z)).atan2(Vec3::dot(outward_normal, X));
                 let u = (angle + consts::PI) / (2.0 * consts::PI);
                 let v = (Vec3::dot(outward_normal, Y) + 1.0) / 2.0;
-                return Some(HitRecord::new(
-                    t_hit,
-                    ray.at(t_hit),
-                    outward_normal,
-                    mat,
-                    ray,
-                    u,
-                    v,
-                ));
+                let tangent = Vec3::new(-outward_normal.z(), 0.0, outward_normal.x());
+                let bitangent = Y - outward_normal.y() * outward_normal;
+                return Some(
+                    HitRecord::new(t_hit, ray.at(t_hit), outward_normal, mat, ray, u, v)
+                        .with_tangents(tangent, bitangent),
+                );
             }
             let t_hit = (-half_b + root) / a;
             if t_hit < t_max && t_hit > t_min {
@@ -64,15 +68,12 @@ impl Sphere {
                 let angle = (Vec3::dot(outward_normal, Z)).atan2(Vec3::dot(outward_normal, X));
                 let u = (angle + consts::PI) / (2.0 * consts::PI);
                 let v = (Vec3::dot(outward_normal, Y) + 1.0) / 2.0;
-                return Some(HitRecord::new(
-                    t_hit,
-                    ray.at(t_hit),
-                    outward_normal,
-                    mat,
-                    ray,
-                    u,
-                    v,
-                ));
+                let tangent = Vec3::new(-outward_normal.z(), 0.0, outward_normal.x());
+                let bitangent = Y - outward_normal.y() * outward_normal;
+                return Some(
+                    HitRecord::new(t_hit, ray.at(t_hit), outward_normal, mat, ray, u, v)
+                        .with_tangents(tangent, bitangent),
+                );
             }
             return None;
         } else {
@@ -80,3 +81,838 @@ impl Sphere {
         }
     }
 }
+
+// Half-thickness used to pad the bounding boxes of flat primitives
+const PAD: f64 = 0.0001;
+
+// Shared intersection for the three rect orientations. `k_axis` is the axis the
+// rect is perpendicular to, `a_axis` and `b_axis` span the rect and give u and v.
+fn hit_rect<'a>(
+    axes: (u8, u8, u8),
+    bounds: (f64, f64, f64, f64, f64),
+    flip: bool,
+    mat: &'a MaterialType,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let (a_axis, b_axis, k_axis) = axes;
+    let (a0, a1, b0, b1, k) = bounds;
+
+    let t = (k - ray.origin().comp(k_axis)) / ray.direction().comp(k_axis);
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+    let p = ray.at(t);
+    let a = p.comp(a_axis);
+    let b = p.comp(b_axis);
+    if a < a0 || a > a1 || b < b0 || b > b1 {
+        return None;
+    }
+
+    let sign = if flip { -1.0 } else { 1.0 };
+    let outward_normal = match k_axis {
+        0 => Vec3::new(sign, 0.0, 0.0),
+        1 => Vec3::new(0.0, sign, 0.0),
+        _ => Vec3::new(0.0, 0.0, sign),
+    };
+    let u = (a - a0) / (a1 - a0);
+    let v = (b - b0) / (b1 - b0);
+    let axis = |i: u8| match i {
+        0 => Vec3::new(1.0, 0.0, 0.0),
+        1 => Vec3::new(0.0, 1.0, 0.0),
+        _ => Vec3::new(0.0, 0.0, 1.0),
+    };
+
+    Some(
+        HitRecord::new(t, p, outward_normal, mat, ray, u, v)
+            .with_tangents(axis(a_axis), axis(b_axis)),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XYRect {
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64,
+    k: f64,
+    flip: bool,
+}
+
+impl XYRect {
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64) -> Self {
+        Self {
+            x0,
+            x1,
+            y0,
+            y1,
+            k,
+            flip: false,
+        }
+    }
+
+    // Same rect with the outward normal pointing along -z
+    pub fn flipped(self) -> Self {
+        Self {
+            flip: !self.flip,
+            ..self
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        hit_rect(
+            (0, 1, 2),
+            (self.x0, self.x1, self.y0, self.y1, self.k),
+            self.flip,
+            mat,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::new(
+            Vec3::new(self.x0, self.y0, self.k - PAD),
+            Vec3::new(self.x1, self.y1, self.k + PAD),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XZRect {
+    x0: f64,
+    x1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    flip: bool,
+}
+
+impl XZRect {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64) -> Self {
+        Self {
+            x0,
+            x1,
+            z0,
+            z1,
+            k,
+            flip: false,
+        }
+    }
+
+    // Same rect with the outward normal pointing along -y
+    pub fn flipped(self) -> Self {
+        Self {
+            flip: !self.flip,
+            ..self
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        hit_rect(
+            (0, 2, 1),
+            (self.x0, self.x1, self.z0, self.z1, self.k),
+            self.flip,
+            mat,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::new(
+            Vec3::new(self.x0, self.k - PAD, self.z0),
+            Vec3::new(self.x1, self.k + PAD, self.z1),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct YZRect {
+    y0: f64,
+    y1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    flip: bool,
+}
+
+impl YZRect {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64) -> Self {
+        Self {
+            y0,
+            y1,
+            z0,
+            z1,
+            k,
+            flip: false,
+        }
+    }
+
+    // Same rect with the outward normal pointing along -x
+    pub fn flipped(self) -> Self {
+        Self {
+            flip: !self.flip,
+            ..self
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        hit_rect(
+            (1, 2, 0),
+            (self.y0, self.y1, self.z0, self.z1, self.k),
+            self.flip,
+            mat,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::new(
+            Vec3::new(self.k - PAD, self.y0, self.z0),
+            Vec3::new(self.k + PAD, self.y1, self.z1),
+        )
+    }
+}
+
+// An axis-aligned box made of six rects with outward facing normals
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+    xy: [XYRect; 2],
+    xz: [XZRect; 2],
+    yz: [YZRect; 2],
+}
+
+impl Cuboid {
+    pub fn new(p0: Vec3, p1: Vec3) -> Self {
+        let min = Vec3::new(p0.x().min(p1.x()), p0.y().min(p1.y()), p0.z().min(p1.z()));
+        let max = Vec3::new(p0.x().max(p1.x()), p0.y().max(p1.y()), p0.z().max(p1.z()));
+
+        Self {
+            min,
+            max,
+            xy: [
+                XYRect::new(min.x(), max.x(), min.y(), max.y(), max.z()),
+                XYRect::new(min.x(), max.x(), min.y(), max.y(), min.z()).flipped(),
+            ],
+            xz: [
+                XZRect::new(min.x(), max.x(), min.z(), max.z(), max.y()),
+                XZRect::new(min.x(), max.x(), min.z(), max.z(), min.y()).flipped(),
+            ],
+            yz: [
+                YZRect::new(min.y(), max.y(), min.z(), max.z(), max.x()),
+                YZRect::new(min.y(), max.y(), min.z(), max.z(), min.x()).flipped(),
+            ],
+        }
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.max
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let mut closest_so_far = t_max;
+        let mut rec = None;
+
+        for r in &self.xy {
+            if let Some(side) = r.hit(mat, ray, t_min, closest_so_far) {
+                closest_so_far = side.t;
+                rec = Some(side);
+            }
+        }
+        for r in &self.xz {
+            if let Some(side) = r.hit(mat, ray, t_min, closest_so_far) {
+                closest_so_far = side.t;
+                rec = Some(side);
+            }
+        }
+        for r in &self.yz {
+            if let Some(side) = r.hit(mat, ray, t_min, closest_so_far) {
+                closest_so_far = side.t;
+                rec = Some(side);
+            }
+        }
+
+        rec
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::new(self.min, self.max)
+    }
+}
+
+// An infinite plane through `point`. It has no bounding box, so it is never
+// put in the BVH.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    u_axis: Vec3,
+    v_axis: Vec3,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.to_unit();
+        let (u_axis, v_axis) = Vec3::basis(normal);
+        Self {
+            point,
+            normal,
+            u_axis,
+            v_axis,
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let denom = Vec3::dot(ray.direction(), self.normal);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = Vec3::dot(self.point - ray.origin(), self.normal) / denom;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        // UVs are distances along the plane, so textures tile in world units
+        let p = ray.at(t);
+        let u = Vec3::dot(p - self.point, self.u_axis);
+        let v = Vec3::dot(p - self.point, self.v_axis);
+        Some(
+            HitRecord::new(t, p, self.normal, mat, ray, u, v)
+                .with_tangents(self.u_axis, self.v_axis),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f64,
+    u_axis: Vec3,
+    v_axis: Vec3,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64) -> Self {
+        let normal = normal.to_unit();
+        let (u_axis, v_axis) = Vec3::basis(normal);
+        Self {
+            center,
+            normal,
+            radius,
+            u_axis,
+            v_axis,
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let denom = Vec3::dot(ray.direction(), self.normal);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = Vec3::dot(self.center - ray.origin(), self.normal) / denom;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+        let p = ray.at(t);
+        let offset = p - self.center;
+        let dist = offset.length();
+        if dist > self.radius {
+            return None;
+        }
+
+        let angle = Vec3::dot(offset, self.v_axis).atan2(Vec3::dot(offset, self.u_axis));
+        let u = (angle + consts::PI) / (2.0 * consts::PI);
+        let v = dist / self.radius;
+        Some(
+            HitRecord::new(t, p, self.normal, mat, ray, u, v)
+                .with_tangents(Vec3::cross(self.normal, offset), offset),
+        )
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        let n = self.normal;
+        let extent = |c: f64| self.radius * (1.0 - c * c).max(0.0).sqrt() + PAD;
+        let e = Vec3::new(extent(n.x()), extent(n.y()), extent(n.z()));
+        AABB::new(self.center - e, self.center + e)
+    }
+}
+
+// A cylinder standing on `base`, along +y. Use an instance to orient it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cylinder {
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, radius: f64, height: f64, capped: bool) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            capped,
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let o = ray.origin() - self.base;
+        let d = ray.direction();
+        let mut closest_so_far = t_max;
+        let mut rec = None;
+
+        let a = d.x() * d.x() + d.z() * d.z();
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        for t in solve_quadratic(a, 2.0 * half_b, c).iter() {
+            if !(*t > t_min && *t < closest_so_far) {
+                continue;
+            }
+            let y = o.y() + t * d.y();
+            if y < 0.0 || y > self.height {
+                continue;
+            }
+            let p = ray.at(*t);
+            let local = p - self.base;
+            let outward_normal = Vec3::new(local.x(), 0.0, local.z()) / self.radius;
+            let u = (local.z().atan2(local.x()) + consts::PI) / (2.0 * consts::PI);
+            let v = y / self.height;
+            let tangent = Vec3::new(-local.z(), 0.0, local.x());
+            closest_so_far = *t;
+            rec = Some(
+                HitRecord::new(*t, p, outward_normal, mat, ray, u, v)
+                    .with_tangents(tangent, Vec3::new(0.0, 1.0, 0.0)),
+            );
+        }
+
+        if self.capped {
+            for (y, sign) in &[(0.0, -1.0), (self.height, 1.0)] {
+                if let Some(cap) =
+                    hit_cap(o, *y, *sign, self.radius, mat, ray, t_min, closest_so_far)
+                {
+                    closest_so_far = cap.t;
+                    rec = Some(cap);
+                }
+            }
+        }
+
+        rec
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::new(
+            self.base - Vec3::new(self.radius, 0.0, self.radius),
+            self.base + Vec3::new(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+// A cone with its base disk on `base` and its apex `height` above it along +y
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cone {
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, radius: f64, height: f64, capped: bool) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            capped,
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let o = ray.origin() - self.base;
+        let d = ray.direction();
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let mut closest_so_far = t_max;
+        let mut rec = None;
+
+        // x^2 + z^2 = k^2 (h - y)^2
+        let h_o = self.height - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let b = 2.0 * (o.x() * d.x() + o.z() * d.z() + k2 * h_o * d.y());
+        let c = o.x() * o.x() + o.z() * o.z() - k2 * h_o * h_o;
+        for t in solve_quadratic(a, b, c).iter() {
+            if !(*t > t_min && *t < closest_so_far) {
+                continue;
+            }
+            let y = o.y() + t * d.y();
+            if y < 0.0 || y > self.height {
+                continue;
+            }
+            let p = ray.at(*t);
+            let local = p - self.base;
+            let outward_normal = Vec3::new(local.x(), k2 * (self.height - y), local.z()).to_unit();
+            let u = (local.z().atan2(local.x()) + consts::PI) / (2.0 * consts::PI);
+            let v = y / self.height;
+            let tangent = Vec3::new(-local.z(), 0.0, local.x());
+            closest_so_far = *t;
+            rec = Some(
+                HitRecord::new(*t, p, outward_normal, mat, ray, u, v)
+                    .with_tangents(tangent, Vec3::new(0.0, 1.0, 0.0)),
+            );
+        }
+
+        if self.capped {
+            if let Some(cap) = hit_cap(o, 0.0, -1.0, self.radius, mat, ray, t_min, closest_so_far) {
+                rec = Some(cap);
+            }
+        }
+
+        rec
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::new(
+            self.base - Vec3::new(self.radius, 0.0, self.radius),
+            self.base + Vec3::new(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+// Horizontal cap of a cylinder or cone at height `y` above the base. `o` is the
+// ray origin relative to the base.
+fn hit_cap<'a>(
+    o: Vec3,
+    y: f64,
+    sign: f64,
+    radius: f64,
+    mat: &'a MaterialType,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let d = ray.direction();
+    if d.y().abs() < 1e-12 {
+        return None;
+    }
+    let t = (y - o.y()) / d.y();
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+    let x = o.x() + t * d.x();
+    let z = o.z() + t * d.z();
+    if x * x + z * z > radius * radius {
+        return None;
+    }
+
+    let u = (x / radius + 1.0) / 2.0;
+    let v = (z / radius + 1.0) / 2.0;
+    Some(
+        HitRecord::new(t, ray.at(t), Vec3::new(0.0, sign, 0.0), mat, ray, u, v)
+            .with_tangents(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    )
+}
+
+// A torus around the y axis through `center`. `major` is the distance from the
+// center to the middle of the tube, `minor` is the radius of the tube.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Torus {
+    center: Vec3,
+    major: f64,
+    minor: f64,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, major: f64, minor: f64) -> Self {
+        Self {
+            center,
+            major,
+            minor,
+        }
+    }
+
+    pub fn hit<'a>(
+        &self,
+        mat: &'a MaterialType,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let len = ray.direction().length();
+        let d = ray.direction() / len;
+        let o = ray.origin() - self.center;
+
+        // Solve from a point close to the torus to keep the quartic well conditioned
+        let shift = (-Vec3::dot(o, d) - (self.major + self.minor)).max(0.0);
+        let o = o + shift * d;
+
+        let r2 = self.major * self.major;
+        let e = o.length_squared() - r2 - self.minor * self.minor;
+        let n = Vec3::dot(o, d);
+        let roots = solve_quartic(
+            1.0,
+            4.0 * n,
+            4.0 * n * n + 2.0 * e + 4.0 * r2 * d.y() * d.y(),
+            4.0 * n * e + 8.0 * r2 * o.y() * d.y(),
+            e * e + 4.0 * r2 * (o.y() * o.y() - self.minor * self.minor),
+        );
+
+        let mut closest: Option<f64> = None;
+        for tau in roots.iter() {
+            let t = (shift + tau) / len;
+            if t > t_min && t < t_max && closest.map_or(true, |c| t < c) {
+                closest = Some(t);
+            }
+        }
+
+        closest.map(|t| {
+            let p = ray.at(t);
+            let local = p - self.center;
+            let ring = Vec3::new(local.x(), 0.0, local.z());
+            let ring_len = ring.length();
+            let tube_center = if ring_len > 0.0 {
+                ring * (self.major / ring_len)
+            } else {
+                Vec3::new(self.major, 0.0, 0.0)
+            };
+            let outward_normal = (local - tube_center) / self.minor;
+            let u = (local.z().atan2(local.x()) + consts::PI) / (2.0 * consts::PI);
+            let v = (local.y().atan2(ring_len - self.major) + consts::PI) / (2.0 * consts::PI);
+            let ring_dir = tube_center / self.major;
+            let tangent = Vec3::new(-local.z(), 0.0, local.x());
+            let bitangent = Vec3::dot(outward_normal, ring_dir) * Vec3::new(0.0, 1.0, 0.0)
+                - outward_normal.y() * ring_dir;
+            HitRecord::new(t, p, outward_normal, mat, ray, u, v).with_tangents(tangent, bitangent)
+        })
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        let r = self.major + self.minor;
+        let e = Vec3::new(r, self.minor, r);
+        AABB::new(self.center - e, self.center + e)
+    }
+}
+
+// Real roots of small polynomials, highest degree coefficient first. Based on
+// the closed form solutions from Graphics Gems (Schwarze, "Cubic and Quartic Roots").
+struct Roots {
+    values: [f64; 4],
+    len: usize,
+}
+
+impl Roots {
+    fn new() -> Self {
+        Roots {
+            values: [0.0; 4],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, x: f64) {
+        self.values[self.len] = x;
+        self.len += 1;
+    }
+
+    fn iter(&self) -> std::slice::Iter<f64> {
+        self.values[..self.len].iter()
+    }
+}
+
+const EQN_EPS: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x > -EQN_EPS && x < EQN_EPS
+}
+
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Roots {
+    let mut roots = Roots::new();
+    if a.abs() < 1e-12 {
+        if b.abs() > 1e-12 {
+            roots.push(-c / b);
+        }
+        return roots;
+    }
+
+    let p = b / (2.0 * a);
+    let q = c / a;
+    let discriminant = p * p - q;
+    if is_zero(discriminant) {
+        roots.push(-p);
+    } else if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        roots.push(-p - root);
+        roots.push(-p + root);
+    }
+    roots
+}
+
+fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Roots {
+    let mut roots = Roots::new();
+    if a.abs() < 1e-12 {
+        return solve_quadratic(b, c, d);
+    }
+
+    // x^3 + A x^2 + B x + C = 0, substitute x = y - A/3 to remove the quadratic term
+    let (a_, b_, c_) = (b / a, c / a, d / a);
+    let sq_a = a_ * a_;
+    let p = (-sq_a / 3.0 + b_) / 3.0;
+    let q = (2.0 / 27.0 * a_ * sq_a - a_ * b_ / 3.0 + c_) / 2.0;
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+
+    if is_zero(discriminant) {
+        if is_zero(q) {
+            roots.push(0.0);
+        } else {
+            let u = (-q).cbrt();
+            roots.push(2.0 * u);
+            roots.push(-u);
+        }
+    } else if discriminant < 0.0 {
+        let phi = (-q / (-cb_p).sqrt()).max(-1.0).min(1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        roots.push(t * phi.cos());
+        roots.push(-t * (phi + consts::PI / 3.0).cos());
+        roots.push(-t * (phi - consts::PI / 3.0).cos());
+    } else {
+        let root = discriminant.sqrt();
+        let u = (root - q).cbrt();
+        let v = -(root + q).cbrt();
+        roots.push(u + v);
+    }
+
+    let sub = a_ / 3.0;
+    for i in 0..roots.len {
+        roots.values[i] -= sub;
+    }
+    roots
+}
+
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Roots {
+    let mut roots = Roots::new();
+    if a.abs() < 1e-12 {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // x^4 + A x^3 + B x^2 + C x + D = 0, substitute x = y - A/4
+    let (a_, b_, c_, d_) = (b / a, c / a, d / a, e / a);
+    let sq_a = a_ * a_;
+    let p = -3.0 / 8.0 * sq_a + b_;
+    let q = sq_a * a_ / 8.0 - a_ * b_ / 2.0 + c_;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b_ / 16.0 - a_ * c_ / 4.0 + d_;
+
+    if is_zero(r) {
+        // y (y^3 + p y + q) = 0
+        for y in solve_cubic(1.0, 0.0, p, q).iter() {
+            roots.push(*y);
+        }
+        roots.push(0.0);
+    } else {
+        // Solve the resolvent cubic, then split into two quadratics
+        let resolvent = solve_cubic(1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0);
+        let z = match resolvent.iter().next() {
+            Some(z) => *z,
+            None => return roots,
+        };
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return roots;
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return roots;
+        };
+
+        let v_signed = if q < 0.0 { -v } else { v };
+        for y in solve_quadratic(1.0, v_signed, z - u).iter() {
+            roots.push(*y);
+        }
+        for y in solve_quadratic(1.0, -v_signed, z + u).iter() {
+            roots.push(*y);
+        }
+    }
+
+    // Resubstitute, then polish with a couple of Newton steps on the original polynomial
+    let sub = a_ / 4.0;
+    for i in 0..roots.len {
+        let mut x = roots.values[i] - sub;
+        for _ in 0..2 {
+            let f = (((x + a_) * x + b_) * x + c_) * x + d_;
+            let df = ((4.0 * x + 3.0 * a_) * x + 2.0 * b_) * x + c_;
+            if df.abs() > 1e-12 {
+                x -= f / df;
+            }
+        }
+        roots.values[i] = x;
+    }
+    roots
+}
@@ -0,0 +1,120 @@
+use crate::rand::Rng;
+use crate::vec::Vec3;
+
+// Visible range wavelengths are sampled from, in nanometers
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+// Wavelength refractive indices are quoted at when not rendering spectrally
+// (the sodium D line)
+const LAMBDA_D: f64 = 589.3;
+
+// Refractive index as a function of wavelength
+#[derive(Clone, Copy, Debug)]
+pub enum Ior {
+    Constant(f64),
+    Cauchy(f64, f64),              // A, B in square micrometers
+    Sellmeier([f64; 3], [f64; 3]), // B coefficients, C coefficients in square micrometers
+}
+
+impl Ior {
+    pub fn bk7() -> Self {
+        Ior::Sellmeier(
+            [1.03961212, 0.231792344, 1.01046945],
+            [0.00600069867, 0.0200179144, 103.560653],
+        )
+    }
+
+    // Dense flint glass (SF11), strongly dispersive
+    pub fn flint() -> Self {
+        Ior::Sellmeier(
+            [1.73759695, 0.313747346, 1.89878101],
+            [0.013188707, 0.0623068142, 155.23629],
+        )
+    }
+
+    pub fn diamond() -> Self {
+        Ior::Sellmeier([4.3356, 0.3306, 0.0], [0.011236, 0.030625, 0.0])
+    }
+
+    pub fn water() -> Self {
+        Ior::Cauchy(1.3199, 0.00610)
+    }
+
+    // Index at a wavelength in nanometers, or at the D line for RGB rendering
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let um = wavelength.unwrap_or(LAMBDA_D) / 1000.0;
+        let um2 = um * um;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy(a, b) => a + b / um2,
+            Ior::Sellmeier(b, c) => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * um2 / (um2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+}
+
+impl From<f64> for Ior {
+    fn from(n: f64) -> Self {
+        Ior::Constant(n)
+    }
+}
+
+fn gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 2 degree color matching functions, using the multi-lobe Gaussian
+// fit from Wyman, Sloan and Shirley (2013)
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+// CIE XYZ to linear sRGB (D65 white)
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
+}
+
+// Turns the RGB result of a path traced at a single wavelength into that
+// wavelength's share of the pixel color. Scenes are still described in RGB,
+// so the path color acts as a filter on the matching functions. Weights are
+// normalized so that a path which does not depend on wavelength keeps its
+// color on average.
+pub struct SpectralFilter {
+    scale: Vec3,
+}
+
+impl SpectralFilter {
+    pub fn new() -> Self {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let total = (0..steps)
+            .map(|i| xyz_to_rgb(cie_xyz(LAMBDA_MIN + i as f64 + 0.5)))
+            .fold(Vec3::new(0.0, 0.0, 0.0), |acc, c| acc + c);
+        let mean = total / steps as f64;
+        SpectralFilter {
+            scale: Vec3::new(1.0 / mean.x(), 1.0 / mean.y(), 1.0 / mean.z()),
+        }
+    }
+
+    pub fn sample_wavelength<T: Rng>(&self, rng: &mut T) -> f64 {
+        rng.gen_range(LAMBDA_MIN, LAMBDA_MAX)
+    }
+
+    pub fn weight(&self, lambda: f64) -> Vec3 {
+        xyz_to_rgb(cie_xyz(lambda)) * self.scale
+    }
+}
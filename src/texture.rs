@@ -1,40 +1,183 @@
+use crate::image::{Filter, Image, Wrap};
+use crate::perlin::Perlin;
 use crate::vec::Vec3;
+use std::sync::Arc;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum TextureType {
     Constant(Vec3),
     JankChecker(f64, Vec3, Vec3), // size, odd, even
     Checker(f64, Vec3, Vec3),     // size, odd, even
+    Image(Arc<Image>, Filter, Wrap),
+    Noise(Arc<Perlin>, f64),                                   // noise, scale
+    Turbulence(Arc<Perlin>, f64, u32),                         // noise, scale, octaves
+    Marble(Arc<Perlin>, f64, u32, Vec3, Vec3),                 // noise, scale, octaves, base, vein
+    Wood(Arc<Perlin>, f64, u32, Vec3, Vec3),                   // noise, scale, octaves, light, dark
+    Mix(Arc<TextureType>, Arc<TextureType>, Arc<TextureType>), // a, b, mask (0 is all a)
+    Multiply(Arc<TextureType>, Arc<TextureType>),
+    ScaleOffset(Arc<TextureType>, f64, f64, f64, f64), // child, u scale, v scale, u offset, v offset
+    Rotate(Arc<TextureType>, f64),                     // child, degrees around uv (0.5, 0.5)
+    Ramp(Arc<TextureType>, Vec<(f64, Vec3)>),          // input, stops sorted by position
+}
+
+impl TextureType {
+    pub fn image(image: Arc<Image>) -> Self {
+        TextureType::Image(image, Filter::Bilinear, Wrap::Repeat)
+    }
+
+    pub fn mix(a: TextureType, b: TextureType, mask: TextureType) -> Self {
+        TextureType::Mix(Arc::new(a), Arc::new(b), Arc::new(mask))
+    }
+
+    pub fn multiply(a: TextureType, b: TextureType) -> Self {
+        TextureType::Multiply(Arc::new(a), Arc::new(b))
+    }
+
+    pub fn scale_offset(child: TextureType, scale: (f64, f64), offset: (f64, f64)) -> Self {
+        TextureType::ScaleOffset(Arc::new(child), scale.0, scale.1, offset.0, offset.1)
+    }
+
+    pub fn rotate(child: TextureType, degrees: f64) -> Self {
+        TextureType::Rotate(Arc::new(child), degrees)
+    }
+
+    // Maps the scalar value of `input` onto a color gradient
+    pub fn ramp(input: TextureType, mut stops: Vec<(f64, Vec3)>) -> Self {
+        assert!(stops.len() > 0, "Color ramp needs at least one stop");
+        assert!(
+            stops.iter().all(|(position, _)| !position.is_nan()),
+            "Color ramp stop position is NaN"
+        );
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        TextureType::Ramp(Arc::new(input), stops)
+    }
+
+    pub fn noise(seed: u64, scale: f64) -> Self {
+        TextureType::Noise(Arc::new(Perlin::new(seed)), scale)
+    }
+
+    pub fn turbulence(seed: u64, scale: f64) -> Self {
+        TextureType::Turbulence(Arc::new(Perlin::new(seed)), scale, 7)
+    }
+
+    pub fn marble(seed: u64, scale: f64) -> Self {
+        TextureType::Marble(
+            Arc::new(Perlin::new(seed)),
+            scale,
+            7,
+            Vec3::new(0.9, 0.9, 0.88),
+            Vec3::new(0.2, 0.2, 0.25),
+        )
+    }
+
+    pub fn wood(seed: u64, scale: f64) -> Self {
+        TextureType::Wood(
+            Arc::new(Perlin::new(seed)),
+            scale,
+            4,
+            Vec3::new(0.75, 0.5, 0.28),
+            Vec3::new(0.4, 0.22, 0.1),
+        )
+    }
 }
 
 pub trait Texture {
     fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
+
+    // Grayscale value, for textures used as masks or parameters
+    fn scalar(&self, u: f64, v: f64, p: &Vec3) -> f64 {
+        let c = self.value(u, v, p);
+        (c.x() + c.y() + c.z()) / 3.0
+    }
+}
+
+fn ramp_color(stops: &[(f64, Vec3)], t: f64) -> Vec3 {
+    // Ramps built without `ramp` may have no stops at all
+    let first = match stops.first() {
+        Some(first) => *first,
+        None => return Vec3::new(0.0, 0.0, 0.0),
+    };
+    if t <= first.0 {
+        return first.1;
+    }
+    for pair in stops.windows(2) {
+        let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+        if t <= t1 {
+            let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+            return (1.0 - f) * c0 + f * c1;
+        }
+    }
+    stops[stops.len() - 1].1
 }
 
 impl Texture for TextureType {
     fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
-        match *self {
-            TextureType::Constant(color) => color,
+        match self {
+            TextureType::Constant(color) => *color,
 
             TextureType::JankChecker(size, odd, even) => {
                 let sines = (size * p.x()).sin() * (size * p.y()).sin() * (size * p.z()).sin();
                 if sines < 0.0 {
-                    odd
+                    *odd
                 } else {
-                    even
+                    *even
                 }
             }
 
             TextureType::Checker(size, odd, even) => {
                 let sines = (u / size).sin() * (v / size).sin();
                 if sines < 0.0 {
-                    odd
+                    *odd
                 } else {
-                    even
+                    *even
                 }
             }
+
+            TextureType::Image(image, filter, wrap) => image.sample(u, v, *filter, *wrap),
+
+            TextureType::Noise(perlin, scale) => {
+                Vec3::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + perlin.noise(&(*scale * *p)))
+            }
+
+            TextureType::Turbulence(perlin, scale, octaves) => {
+                Vec3::new(1.0, 1.0, 1.0) * perlin.turb(&(*scale * *p), *octaves)
+            }
+
+            TextureType::Marble(perlin, scale, octaves, base, vein) => {
+                // Turbulence shifts the phase of a sine wave to make the veins
+                let q = *scale * *p;
+                let t = 0.5 * (1.0 + (q.z() + 10.0 * perlin.turb(&q, *octaves)).sin());
+                t * *base + (1.0 - t) * *vein
+            }
+
+            TextureType::Wood(perlin, scale, octaves, light, dark) => {
+                // Rings around the y axis, wobbled by turbulence
+                let r = (p.x() * p.x() + p.z() * p.z()).sqrt() * scale
+                    + 2.0 * perlin.turb(&(*scale * 0.2 * *p), *octaves);
+                let t = r - r.floor();
+                t * *dark + (1.0 - t) * *light
+            }
+
+            TextureType::Mix(a, b, mask) => {
+                let t = mask.scalar(u, v, p).max(0.0).min(1.0);
+                (1.0 - t) * a.value(u, v, p) + t * b.value(u, v, p)
+            }
+
+            TextureType::Multiply(a, b) => a.value(u, v, p) * b.value(u, v, p),
+
+            TextureType::ScaleOffset(child, su, sv, ou, ov) => {
+                child.value(u * su + ou, v * sv + ov, p)
+            }
+
+            TextureType::Rotate(child, degrees) => {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let (du, dv) = (u - 0.5, v - 0.5);
+                child.value(0.5 + du * cos - dv * sin, 0.5 + du * sin + dv * cos, p)
+            }
+
+            TextureType::Ramp(input, stops) => ramp_color(stops, input.scalar(u, v, p)),
         }
     }
 }
@@ -58,6 +58,17 @@ impl Vec3 {
         r_out_parallel + r_out_perp
     }
 
+    // Two unit vectors that form an orthonormal basis with the unit vector `n`
+    pub fn basis(n: Vec3) -> (Vec3, Vec3) {
+        let sign = 1.0_f64.copysign(n.z());
+        let a = -1.0 / (sign + n.z());
+        let b = n.x() * n.y() * a;
+        (
+            Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+            Vec3::new(b, sign + n.y() * n.y() * a, -n.y()),
+        )
+    }
+
     pub fn random<T: Rng>(rng: &mut T) -> Vec3 {
         Vec3::new(rng.gen(), rng.gen(), rng.gen())
     }
@@ -115,6 +126,11 @@ impl Vec3 {
             _ => f64::NAN,
         }
     }
+
+    // Perceived brightness of a linear RGB color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
+    }
 }
 
 impl Add<Self> for Vec3 {
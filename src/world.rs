@@ -0,0 +1,157 @@
+use crate::{
+    background::Background,
+    hittable::{HittableList, HittableType},
+    light_sampler::LightSampler,
+    lights::LightType,
+    materials::{HitRecord, MaterialType},
+    photon::PhotonMap,
+    ray::Ray,
+    texture::TextureType,
+    vec::Vec3,
+};
+use rand::Rng;
+
+// A whole scene: the objects in it, the lights that are sampled directly, and
+// what rays see when they escape
+pub struct World {
+    objects: HittableList,
+    lights: Vec<LightType>,
+    emitters: usize, // How many of the lights are objects' surfaces
+    light_sampler: LightSampler,
+    power_sampler: LightSampler, // Picks lights to trace light paths from
+    light_bvh: bool,
+    background: Background,
+    caustics: Option<PhotonMap>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            objects: HittableList::new(),
+            lights: Vec::new(),
+            emitters: 0,
+            light_sampler: LightSampler::power(&[]),
+            power_sampler: LightSampler::power(&[]),
+            light_bvh: false,
+            background: Background::sky(),
+            caustics: None,
+        }
+    }
+
+    pub fn add(&mut self, object: HittableType, mat: MaterialType) {
+        self.objects.add(object, mat)
+    }
+
+    pub fn add_light(&mut self, light: LightType) {
+        self.lights.push(light)
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background
+    }
+
+    // Photons to look caustics up in, rather than tracing paths to them
+    pub fn set_caustics(&mut self, caustics: PhotonMap) {
+        self.caustics = Some(caustics)
+    }
+
+    // Pick lights with a tree over them rather than by power alone, for
+    // scenes with many lights spread around
+    pub fn use_light_bvh(&mut self) {
+        self.light_bvh = true
+    }
+
+    // Also gathers the emissive spheres to sample as lights, each tagged with
+    // the index of its light so hits on it find it again, so call this last
+    // once the world is in place
+    pub fn build_bvh(&mut self, time0: f64, time1: f64) {
+        self.objects.build_bvh(time0, time1);
+
+        self.lights
+            .retain(|light| !matches!(light, LightType::Sphere(..)));
+        self.emitters = 0;
+        let (lights, emitters) = (&mut self.lights, &mut self.emitters);
+        self.objects.for_each_mut(|object, mat, light| {
+            *light = None;
+            if let (
+                HittableType::Sphere(sphere),
+                MaterialType::DiffuseLight(TextureType::Constant(radiance)),
+            ) = (object, mat)
+            {
+                *light = Some(lights.len());
+                *emitters += 1;
+                lights.push(LightType::Sphere(
+                    sphere.center(),
+                    sphere.radius(),
+                    *radiance,
+                ));
+            }
+        });
+
+        self.power_sampler = LightSampler::power(&self.lights);
+        self.light_sampler = if self.light_bvh {
+            LightSampler::bvh(&self.lights)
+        } else {
+            LightSampler::power(&self.lights)
+        };
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.objects.hit(ray, t_min, t_max)
+    }
+
+    // Fraction of light getting from the ray origin to `t_max`, for shadow
+    // rays
+    pub fn transmittance(&self, ray: &Ray, t_max: f64) -> f64 {
+        self.objects.transmittance(ray, 0.001, t_max)
+    }
+
+    pub fn lights(&self) -> &[LightType] {
+        &self.lights
+    }
+
+    // One light that isn't at infinity to sample from `p`, and the probability
+    // it was picked
+    pub fn sample_light<T: Rng>(&self, p: Vec3, rng: &mut T) -> Option<(&LightType, f64)> {
+        let (index, probability) = self.light_sampler.sample(p, rng)?;
+        Some((&self.lights[index], probability))
+    }
+
+    // Density over solid angle of light sampling from `p` finding the surface
+    // `rec` hit along `direction`, zero if it isn't a sampled light
+    pub fn light_pdf(&self, p: Vec3, rec: &HitRecord, direction: Vec3) -> f64 {
+        match rec.light {
+            Some(index) => self.light_sampler.pmf(p, index) * self.lights[index].pdf(p, direction),
+            None => 0.0,
+        }
+    }
+
+    // A light picked by power alone, the same wherever the path is, and the
+    // probability it was picked. Lights at infinity are never picked.
+    pub fn sample_light_by_power<T: Rng>(&self, rng: &mut T) -> Option<(&LightType, f64)> {
+        let (index, probability) = self.power_sampler.sample(Vec3::new(0.0, 0.0, 0.0), rng)?;
+        Some((&self.lights[index], probability))
+    }
+
+    // The sampled light the surface `rec` hit is part of, and its probability
+    // of being picked by power
+    pub fn emitter(&self, rec: &HitRecord) -> Option<(&LightType, f64)> {
+        let index = rec.light?;
+        let probability = self.power_sampler.pmf(Vec3::new(0.0, 0.0, 0.0), index);
+        Some((&self.lights[index], probability))
+    }
+
+    // Whether anything bounced rays can find is also sampled directly, so
+    // finding it needs weighing against the direct samples
+    pub fn has_sampled_emitters(&self) -> bool {
+        self.emitters > 0 || self.background.is_sampled()
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn caustics(&self) -> Option<&PhotonMap> {
+        self.caustics.as_ref()
+    }
+}